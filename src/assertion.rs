@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use tokio::io;
+
+use crate::snapshot;
+use crate::{Context, Error, SettingsStack};
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
+/// checks on the files after the `test` script passed, the paths are relative to the
+/// directory the test runs in
+pub struct FileAssertions {
+    /// files that have to exist
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files_exist: Vec<PathBuf>,
+    /// files that may not exist
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files_absent: Vec<PathBuf>,
+    /// files with a regex that has to match their content
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_contains: BTreeMap<PathBuf, String>,
+    /// files that are compared to their snapshot, like `snapshot` does for the output
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_matches_snapshot: Vec<PathBuf>,
+    /// directories with the fixture directory they have to be equal to, file by file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dir_matches: BTreeMap<PathBuf, PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// a file that is not what an assertion expects
+pub struct AssertionFailure {
+    /// the key of the assertion, for example `files_exist`
+    pub assertion: &'static str,
+    pub path: PathBuf,
+    pub reason: String,
+    /// unified diff from the fixture to the file
+    pub diff: Option<String>,
+}
+
+impl std::fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} {}: {}",
+            self.assertion,
+            self.path.display(),
+            self.reason
+        )?;
+        if let Some(diff) = &self.diff {
+            write!(f, "{}", diff)?;
+        }
+        Ok(())
+    }
+}
+
+impl FileAssertions {
+    /// the keys in a config file next to the `Test::FIELDS`
+    pub const FIELDS: &[&str] = &[
+        "files_exist",
+        "files_absent",
+        "file_contains",
+        "file_matches_snapshot",
+        "dir_matches",
+    ];
+
+    pub fn is_empty(&self) -> bool {
+        self == &FileAssertions::default()
+    }
+
+    /// checks that the regexes are valid
+    pub fn check(&self) -> Result<(), String> {
+        for pattern in self.file_contains.values() {
+            Regex::new(pattern).map_err(|e| format!("invalid regex `{}`: {}", pattern, e))?;
+        }
+        Ok(())
+    }
+
+    /// runs all the assertions, every one that fails is its own error
+    pub async fn run(&self, settings: &SettingsStack<'_, '_>, ctx: &Context) -> Vec<Error> {
+        let dir = ctx.cwd().unwrap_or_else(|| Path::new(""));
+        let mut errors = Vec::new();
+
+        for path in &self.files_exist {
+            if !dir.join(path).exists() {
+                errors.push(failure("files_exist", path, "the file does not exist"));
+            }
+        }
+        for path in &self.files_absent {
+            if dir.join(path).exists() {
+                errors.push(failure("files_absent", path, "the file exists"));
+            }
+        }
+        for (path, pattern) in &self.file_contains {
+            if let Err(error) = contains(&dir.join(path), pattern).await {
+                errors.push(failure("file_contains", path, error));
+            }
+        }
+        for path in &self.file_matches_snapshot {
+            if let Err(error) = snapshot::check_file(path, settings, ctx).await {
+                errors.push(error);
+            }
+        }
+        for (path, fixture) in &self.dir_matches {
+            match dir_matches(&dir.join(path), &dir.join(fixture)) {
+                Ok(differences) => errors.extend(differences.into_iter().map(|mut failure| {
+                    if !failure.path.as_os_str().is_empty() {
+                        failure.path = path.join(&failure.path);
+                    } else {
+                        failure.path = path.clone();
+                    }
+                    Error::Assertion(failure)
+                })),
+                Err(e) => errors.push(failure("dir_matches", path, e.to_string())),
+            }
+        }
+
+        errors
+    }
+}
+
+fn failure<S: Into<String>>(assertion: &'static str, path: &Path, reason: S) -> Error {
+    Error::Assertion(AssertionFailure {
+        assertion,
+        path: path.to_path_buf(),
+        reason: reason.into(),
+        diff: None,
+    })
+}
+
+async fn contains(path: &Path, pattern: &str) -> Result<(), String> {
+    let regex = Regex::new(pattern).map_err(|e| format!("invalid regex `{}`: {}", pattern, e))?;
+    let content = tokio::fs::read(path)
+        .await
+        .map_err(|e| format!("unable to read the file: {}", e))?;
+    if regex.is_match(&String::from_utf8_lossy(&content)) {
+        Ok(())
+    } else {
+        Err(format!("the content does not match `{}`", pattern))
+    }
+}
+
+/// the files that are missing, extra or different in `dir` compared to `fixture`, with paths
+/// relative to `dir`
+fn dir_matches(dir: &Path, fixture: &Path) -> io::Result<Vec<AssertionFailure>> {
+    let difference = |path: &Path, reason: &str| AssertionFailure {
+        assertion: "dir_matches",
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+        diff: None,
+    };
+    if !fixture.is_dir() {
+        return Err(io::Error::other(format!(
+            "the fixture {} is not a directory",
+            fixture.display()
+        )));
+    }
+    if !dir.is_dir() {
+        return Ok(vec![difference(
+            Path::new(""),
+            "the directory does not exist",
+        )]);
+    }
+
+    let files = files_in(dir)?;
+    let expected = files_in(fixture)?;
+    let mut failures = Vec::new();
+    for path in expected.union(&files) {
+        match (expected.contains(path), files.contains(path)) {
+            (true, false) => failures.push(difference(path, "the file is missing")),
+            (false, true) => failures.push(difference(path, "the file is not in the fixture")),
+            _ => {
+                let want = std::fs::read(fixture.join(path))?;
+                let got = std::fs::read(dir.join(path))?;
+                if want == got {
+                    continue;
+                }
+                let mut different = difference(path, "the file is different from the fixture");
+                if let (Ok(want), Ok(got)) = (std::str::from_utf8(&want), std::str::from_utf8(&got))
+                {
+                    different.diff = Some(
+                        TextDiff::from_lines(want, got)
+                            .unified_diff()
+                            .header("fixture", "file")
+                            .to_string(),
+                    );
+                }
+                failures.push(different);
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// all the files below `dir`, relative to it
+fn files_in(dir: &Path) -> io::Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        for entry in std::fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else {
+                files.insert(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[test]
+fn dir_matches_reports_every_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let (out, fixture) = (dir.path().join("out"), dir.path().join("fixture"));
+    for (path, content) in [
+        (out.join("same.txt"), "same\n"),
+        (fixture.join("same.txt"), "same\n"),
+        (out.join("nested/changed.txt"), "one\ntwo\n"),
+        (fixture.join("nested/changed.txt"), "one\n2\n"),
+        (out.join("extra.txt"), ""),
+        (fixture.join("missing.txt"), ""),
+    ] {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let failures = dir_matches(&out, &fixture).unwrap();
+    let reasons: Vec<(PathBuf, &str)> = failures
+        .iter()
+        .map(|failure| (failure.path.clone(), failure.reason.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            (PathBuf::from("extra.txt"), "the file is not in the fixture"),
+            (PathBuf::from("missing.txt"), "the file is missing"),
+            (
+                PathBuf::from("nested/changed.txt"),
+                "the file is different from the fixture"
+            ),
+        ],
+        reasons
+    );
+    assert!(failures[2]
+        .diff
+        .as_deref()
+        .unwrap()
+        .contains(" one\n-2\n+two\n"));
+}
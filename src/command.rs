@@ -1,9 +1,13 @@
-use std::process::Output;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use tokio::io;
-use tokio::process::Command;
 
 use crate::error::Result;
+use crate::executor::{container_runtime, Container, Executor, Invocation, Process};
+use crate::output::{self, CommandOutput, ShowOutput, Stream};
+use crate::timing::Phase;
+use crate::Context;
 use crate::Error;
 
 use crate::Data;
@@ -18,18 +22,96 @@ impl<'a> CommandBuilder<'a> {
         CommandBuilder { function }
     }
 
-    async fn run(self, settings: &SettingsStack<'_, '_>) -> io::Result<Output> {
-        let (program, program_args) = settings.command_with_args();
-        let mut command = Command::new(program);
+    fn argv(&self, settings: &SettingsStack<'_, '_>) -> Vec<String> {
+        argv(self.function, settings)
+    }
 
-        if settings.clear_env() {
-            command.env_clear();
-        }
+    /// returns `None` when the command got cancelled
+    async fn run(
+        self,
+        argv: &[String],
+        stdin: Option<Vec<u8>>,
+        settings: &SettingsStack<'_, '_>,
+        ctx: &Context,
+    ) -> io::Result<Option<CommandOutput>> {
+        let invocation = Invocation {
+            program: argv[0].clone(),
+            args: argv[1..].to_vec(),
+            env: settings
+                .env()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            clear_env: settings.clear_env(),
+            cwd: ctx.cwd().map(PathBuf::from),
+            stdin,
+        };
+        let process = match (ctx.executor(), settings.image()) {
+            (Some(executor), _) => executor.spawn(invocation),
+            (None, Some(image)) => Container {
+                runtime: container_runtime().unwrap_or("docker").to_string(),
+                image: image.to_string(),
+                mounts: ctx.config_dir().map(PathBuf::from).into_iter().collect(),
+                shared: if settings.reuse_container() {
+                    Some(ctx.containers().clone())
+                } else {
+                    None
+                },
+                group: ctx.group(),
+            }
+            .spawn(invocation),
+            (None, None) => settings.executor().executor().spawn(invocation),
+        };
+        let Process {
+            stdout,
+            stderr,
+            status,
+        } = process.await?;
 
-        command.envs(settings.env());
-        command.args(program_args);
-        command.arg(self.function);
-        command.output().await
+        let live = ctx.nocapture() || settings.show_output() == ShowOutput::Always;
+        let limit = settings.output_limit() as usize * 1024;
+        let output = async {
+            let (stdout, stderr, status) = tokio::try_join!(
+                output::capture(
+                    stdout,
+                    Stream::Stdout,
+                    ctx,
+                    live,
+                    limit,
+                    ctx.log_path(Stream::Stdout)
+                ),
+                output::capture(
+                    stderr,
+                    Stream::Stderr,
+                    ctx,
+                    live,
+                    limit,
+                    ctx.log_path(Stream::Stderr)
+                ),
+                status
+            )?;
+            Ok(CommandOutput {
+                status,
+                stdout,
+                stderr,
+            })
+        };
+
+        // dropping the output stops the command, and removes its container
+        let timeout = match ctx.phase() {
+            Some(Phase::BeforeAll) | Some(Phase::AfterAll) => settings.setup_timeout(),
+            _ => settings.timeout(),
+        };
+        let output = tokio::time::timeout(Duration::from_millis(timeout.into()), output);
+        tokio::select! {
+            output = output => match output {
+                Ok(output) => output.map(Some),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("the command did not exit within {} ms", timeout),
+                )),
+            },
+            _ = ctx.cancelled() => Ok(None),
+        }
     }
 }
 
@@ -39,20 +121,38 @@ impl std::fmt::Display for Error {
             Error::IO(error) => write!(f, "{}", error),
             Error::PatternError(error) => write!(f, "{}", error),
             Error::GlobError(error) => write!(f, "{}", error),
+            Error::Cancelled => write!(f, "cancelled"),
+            Error::Context(context, error) => {
+                if let Some(phase) = context.phase {
+                    writeln!(f, "phase: {}", phase)?;
+                }
+                let error = error.to_string();
+                write!(f, "{}", error)?;
+                if !error.ends_with('\n') {
+                    writeln!(f)?;
+                }
+                write!(f, "{}", context)
+            }
+            Error::Many(errors) => {
+                for (index, error) in errors.iter().enumerate() {
+                    if index != 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            Error::Step(failure) => write!(f, "{}", failure),
+            Error::Snapshot(failure) => write!(f, "{}", failure),
+            Error::Assertion(failure) => write!(f, "{}", failure),
+            Error::Service(failure) => write!(f, "{}", failure),
             Error::ExitCode(error) => {
-                writeln!(f, "{}", &error.status)?;
-                writeln!(
-                    f,
-                    "stdout:\n{}",
-                    String::from_utf8(error.stdout.clone())
-                        .unwrap_or(String::from("not utf8 string"))
-                )?;
-                writeln!(
-                    f,
-                    "stderr:\n{}",
-                    String::from_utf8(error.stderr.clone())
-                        .unwrap_or(String::from("not utf8 string"))
-                )
+                match error.status.code() {
+                    Some(code) => writeln!(f, "exit code: {}", code)?,
+                    None => writeln!(f, "{}", &error.status)?,
+                }
+                writeln!(f, "stdout:\n{}", error.stdout)?;
+                writeln!(f, "stderr:\n{}", error.stderr)
             }
         }
     }
@@ -60,38 +160,116 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
-pub async fn pre_setup(data: &Data) -> Option<Result> {
+pub async fn pre_setup(data: &Data, ctx: &Context) -> Option<Result> {
     if let Some(before_all) = &data.setup.before_all {
         let settings = data.global.stack(&[]);
-        return Some(run(before_all, &settings).await.into());
+        let ctx = ctx.for_phase(Phase::BeforeAll);
+        ctx.phase_started();
+        return Some(run(before_all, &settings, &ctx).await);
     }
 
     None
 }
 
-pub async fn post_setup(data: &Data) -> Option<Result> {
+pub async fn post_setup(data: &Data, ctx: &Context) -> Option<Result> {
     if let Some(after_all) = &data.setup.after_all {
         let settings = data.global.stack(&[]);
-        return Some(run(after_all, &settings).await.into());
+        let ctx = ctx.for_phase(Phase::AfterAll);
+        ctx.phase_started();
+        return Some(run(after_all, &settings, &ctx).await);
     }
 
     None
 }
 
-pub async fn run<'a, 'b>(command: &str, settings: &SettingsStack<'a, 'b>) -> Result {
-    // let result = Command::new(program)
-    //     .args(program_args)
-    //     .arg(command)
-    let result = CommandBuilder::new(command).run(settings).await;
+pub async fn run<'a, 'b>(command: &str, settings: &SettingsStack<'a, 'b>, ctx: &Context) -> Result {
+    run_with_output(command, settings, ctx).await.map(|_| ())
+}
+
+/// like `run`, but returns the output when the command succeeds. When it fails the output is
+/// part of the error
+pub async fn run_with_output<'a, 'b>(
+    command: &str,
+    settings: &SettingsStack<'a, 'b>,
+    ctx: &Context,
+) -> std::result::Result<CommandOutput, Error> {
+    run_with_input(command, None, settings, ctx).await
+}
+
+/// like `run_with_output`, with `stdin` as the input of the command. `should_fail` only
+/// turns around the result of the `test` phase, the other scripts still have to succeed
+pub async fn run_with_input<'a, 'b>(
+    command: &str,
+    stdin: Option<Vec<u8>>,
+    settings: &SettingsStack<'a, 'b>,
+    ctx: &Context,
+) -> std::result::Result<CommandOutput, Error> {
+    let builder = CommandBuilder::new(command);
+    let argv = builder.argv(settings);
+    let result = builder.run(&argv, stdin, settings, ctx).await;
 
-    match result {
-        Ok(Output { status, .. }) if is_success(settings.should_fail(), status.success()) => Ok(()),
-        Ok(error) => Err(Error::ExitCode(error)),
+    let result = match result {
+        Ok(None) => Err(Error::Cancelled),
+        Ok(Some(mut output)) => {
+            if settings.show_output() == ShowOutput::Never {
+                output.stdout.clear();
+                output.stderr.clear();
+            }
+            let should_fail = settings.should_fail() && ctx.phase() == Some(Phase::Test);
+            if is_success(should_fail, output.status.success()) {
+                for log in [&mut output.stdout.log, &mut output.stderr.log] {
+                    if let Some(log) = log.take() {
+                        let _ = tokio::fs::remove_file(log).await;
+                    }
+                }
+                Ok(output)
+            } else {
+                Err(Error::ExitCode(output))
+            }
+        }
         Err(err) => Err(Error::IO(err)),
+    };
+
+    result.map_err(|error| with_context(error, argv, settings, ctx))
+}
+
+/// the program with its arguments, the script is the last argument
+pub(crate) fn argv(script: &str, settings: &SettingsStack<'_, '_>) -> Vec<String> {
+    let (program, program_args) = settings.command_with_args();
+    let mut argv = vec![program];
+    argv.extend(program_args);
+    argv.push(script.to_string());
+    argv
+}
+
+/// adds the test, phase, command and where it ran to the error of a command. With
+/// `show_output: never` the script is left out, it can have the same secrets as the output
+pub(crate) fn with_context(
+    error: Error,
+    mut argv: Vec<String>,
+    settings: &SettingsStack<'_, '_>,
+    ctx: &Context,
+) -> Error {
+    if settings.show_output() == ShowOutput::Never {
+        if let Some(script) = argv.last_mut() {
+            *script = String::from("[hidden by show_output: never]");
+        }
     }
+    error.with_context(|context| {
+        context.test_name = ctx.test_name().map(String::from);
+        context.phase = ctx.phase();
+        context.command = Some(argv);
+        context.cwd = std::env::current_dir()
+            .ok()
+            .map(|current_dir| match ctx.cwd() {
+                Some(cwd) => current_dir.join(cwd),
+                None => current_dir,
+            });
+        context.location = ctx.location().cloned();
+    })
 }
 
-fn is_success(inverse_result: bool, success: bool) -> bool {
+pub(crate) fn is_success(inverse_result: bool, success: bool) -> bool {
     match (inverse_result, success) {
         (false, success) => success,
         (true, success) => !success,
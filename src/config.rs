@@ -0,0 +1,482 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::assertion::FileAssertions;
+use crate::service::Service;
+use crate::settings::{GlobalSettings, Settings};
+use crate::tests::{Group, Test, Tests};
+use crate::{Data, Setup, TestsOrGroup};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// place in a config file, lines start at 1
+pub struct Location {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+}
+
+impl Location {
+    pub fn new<P: Into<PathBuf>>(file: P, line: Option<usize>) -> Location {
+        Location {
+            file: file.into(),
+            line,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.file.display(), line),
+            None => write!(f, "{}", self.file.display()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// the file formats a config can be written in
+pub enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// the format that belongs to the extension of `path`, yaml when the extension is unknown
+    pub fn from_path(path: &Path) -> Format {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| extension.parse().ok())
+            .unwrap_or(Format::Yaml)
+    }
+
+    pub fn to_string<T: Serialize>(&self, value: &T) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(match self {
+            Format::Yaml => serde_yaml::to_string(value)?,
+            Format::Toml => toml::to_string_pretty(value)?,
+            Format::Json => serde_json::to_string_pretty(value)?,
+        })
+    }
+
+    /// reads the config into a yaml value, so the other checks work the same for every format
+    fn to_value(self, source: &str) -> Result<Value, Diagnostic> {
+        match self {
+            Format::Yaml => serde_yaml::from_str(source).map_err(|e| from_serde(&e)),
+            Format::Toml => toml::from_str(source).map_err(|e| {
+                let location = e.line_col().map(|(line, column)| (line + 1, column + 1));
+                diagnostic(e.to_string(), location)
+            }),
+            Format::Json => serde_json::from_str(source)
+                .map_err(|e| diagnostic(e.to_string(), Some((e.line(), e.column())))),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown format `{}`, expected yaml, toml or json",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Yaml => write!(f, "yaml"),
+            Format::Toml => write!(f, "toml"),
+            Format::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// a single problem in a config file, lines and columns start at 1
+pub struct Diagnostic {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// amount of characters to underline
+    pub width: usize,
+    pub help: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// all the problems found in a config file
+pub struct ConfigError {
+    pub file: PathBuf,
+    source: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ConfigError {
+    pub(crate) fn new(file: &Path, source: &str, diagnostics: Vec<Diagnostic>) -> ConfigError {
+        ConfigError {
+            file: file.to_path_buf(),
+            source: source.to_string(),
+            diagnostics,
+        }
+    }
+}
+
+/// config with the test type known, so serde reports the real error instead of "data did not
+/// match any variant"
+#[derive(Deserialize)]
+struct Config<T> {
+    #[serde(default)]
+    setup: Setup,
+    test: T,
+    #[serde(default)]
+    global: GlobalSettings,
+    #[serde(default)]
+    services: BTreeMap<String, Service>,
+}
+
+/// parses a config, unknown keys are an error. Only yaml errors point to the key in the file
+pub fn parse(source: &str, file: &Path, format: Format) -> Result<Data, ConfigError> {
+    let error = |diagnostic| ConfigError::new(file, source, vec![diagnostic]);
+
+    let value = format.to_value(source).map_err(error)?;
+    let is_group = value
+        .get("test")
+        .and_then(Value::as_mapping)
+        .is_some_and(|test| test.contains_key(&Value::from("files")));
+
+    let yaml_source = if format == Format::Yaml { source } else { "" };
+    let mut diagnostics = unknown_keys(&value, is_group, yaml_source);
+
+    match typed_data(source, value, format, is_group) {
+        Ok(data) if diagnostics.is_empty() => Ok(data),
+        Ok(_) => Err(ConfigError::new(file, source, diagnostics)),
+        Err(diagnostic) => {
+            // the unknown keys are already reported, with a suggestion
+            if diagnostics.is_empty() || !diagnostic.message.contains("unknown field") {
+                diagnostics.push(diagnostic);
+            }
+            Err(ConfigError::new(file, source, diagnostics))
+        }
+    }
+}
+
+fn typed_data(
+    source: &str,
+    value: Value,
+    format: Format,
+    is_group: bool,
+) -> Result<Data, Diagnostic> {
+    if is_group {
+        let config: Config<Group> = typed(source, value, format)?;
+        Ok(Data {
+            setup: config.setup,
+            test: TestsOrGroup::Group(config.test),
+            global: config.global,
+            services: config.services,
+        })
+    } else {
+        let config: Config<Tests> = typed(source, value, format)?;
+        Ok(Data {
+            setup: config.setup,
+            test: TestsOrGroup::Tests(config.test),
+            global: config.global,
+            services: config.services,
+        })
+    }
+}
+
+/// yaml is parsed again, so the error has a location
+fn typed<T: DeserializeOwned>(source: &str, value: Value, format: Format) -> Result<T, Diagnostic> {
+    match format {
+        Format::Yaml => serde_yaml::from_str(source),
+        Format::Toml | Format::Json => serde_yaml::from_value(value),
+    }
+    .map_err(|e| from_serde(&e))
+}
+
+fn from_serde(error: &serde_yaml::Error) -> Diagnostic {
+    let location = error
+        .location()
+        .map(|location| (location.line(), location.column()));
+    diagnostic(error.to_string(), location)
+}
+
+fn diagnostic(message: String, location: Option<(usize, usize)>) -> Diagnostic {
+    // the location is shown in the snippet
+    let message = match (message.rfind(" at line "), location) {
+        (Some(index), Some(_)) => message[..index].to_string(),
+        _ => message,
+    };
+
+    Diagnostic {
+        message,
+        line: location.map(|(line, _)| line),
+        column: location.map(|(_, column)| column),
+        width: 1,
+        help: None,
+    }
+}
+
+fn unknown_keys(value: &Value, is_group: bool, source: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let root = match value.as_mapping() {
+        Some(root) => root,
+        None => return diagnostics,
+    };
+
+    check_keys(root, &[], &[Data::FIELDS], source, &mut diagnostics);
+
+    if let Some(setup) = value.get("setup").and_then(Value::as_mapping) {
+        check_keys(
+            setup,
+            &["setup"],
+            &[Setup::FIELDS],
+            source,
+            &mut diagnostics,
+        );
+    }
+
+    if let Some(global) = value.get("global").and_then(Value::as_mapping) {
+        let allowed = [GlobalSettings::FIELDS, Settings::FIELDS];
+        check_keys(global, &["global"], &allowed, source, &mut diagnostics);
+    }
+
+    if let Some(services) = value.get("services").and_then(Value::as_mapping) {
+        for (name, service) in services {
+            if let (Some(name), Some(service)) = (name.as_str(), service.as_mapping()) {
+                let path = ["services", name];
+                check_keys(service, &path, &[Service::FIELDS], source, &mut diagnostics);
+            }
+        }
+    }
+
+    match value.get("test").and_then(Value::as_mapping) {
+        Some(group) if is_group => {
+            let allowed = [Group::FIELDS, Settings::FIELDS];
+            check_keys(group, &["test"], &allowed, source, &mut diagnostics);
+        }
+        Some(tests) => {
+            for (test_name, test) in tests {
+                if let (Some(test_name), Some(test)) = (test_name.as_str(), test.as_mapping()) {
+                    let allowed = [Test::FIELDS, FileAssertions::FIELDS, Settings::FIELDS];
+                    check_keys(
+                        test,
+                        &["test", test_name],
+                        &allowed,
+                        source,
+                        &mut diagnostics,
+                    );
+                }
+            }
+        }
+        None => (),
+    }
+
+    diagnostics
+}
+
+fn check_keys(
+    mapping: &Mapping,
+    path: &[&str],
+    allowed: &[&[&str]],
+    source: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let allowed = allowed.iter().flat_map(|fields| fields.iter().copied());
+
+    for key in mapping.iter().filter_map(|(key, _)| key.as_str()) {
+        if allowed.clone().any(|field| field == key) {
+            continue;
+        }
+
+        let mut key_path = path.to_vec();
+        key_path.push(key);
+        let location = find_key(source, &key_path);
+        let message = match path.last() {
+            Some(parent) => format!("unknown key `{}` in `{}`", key, parent),
+            None => format!("unknown key `{}`", key),
+        };
+        let help = allowed
+            .clone()
+            .map(|field| (strsim::damerau_levenshtein(key, field), field))
+            .filter(|(distance, field)| *distance <= 2.max(field.len() / 3))
+            .min()
+            .map(|(_, field)| format!("did you mean `{}`?", field));
+
+        diagnostics.push(Diagnostic {
+            message,
+            line: location.map(|(line, _)| line),
+            column: location.map(|(_, column)| column),
+            width: key.chars().count(),
+            help,
+        });
+    }
+}
+
+/// finds the line and column of a nested key in a yaml config, for example
+/// `["test", "my test", "timeout"]`
+pub fn find_key(source: &str, path: &[&str]) -> Option<(usize, usize)> {
+    let mut lines = source.lines().enumerate();
+    let mut parent_indent = None;
+    let mut found = None;
+
+    for key in path {
+        let candidates = [
+            format!("{}:", key),
+            format!("\"{}\":", key),
+            format!("'{}':", key),
+        ];
+        let mut child_indent = None;
+        found = None;
+
+        for (index, line) in lines.by_ref() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+                continue;
+            }
+            let indent = line.len() - trimmed.len();
+            match parent_indent {
+                Some(parent_indent) if indent <= parent_indent => return None,
+                None if indent != 0 => continue,
+                _ => (),
+            }
+            if *child_indent.get_or_insert(indent) != indent {
+                continue;
+            }
+            if candidates.iter().any(|c| trimmed.starts_with(c.as_str())) {
+                found = Some((index + 1, indent + 1));
+                parent_indent = Some(indent);
+                break;
+            }
+        }
+        found?;
+    }
+
+    found
+}
+
+/// finds the line of `test_name` under the top level `test:` key of a yaml config
+pub fn find_test_line(source: &str, test_name: &str) -> Option<usize> {
+    find_key(source, &["test", test_name]).map(|(line, _)| line)
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, diagnostic) in self.diagnostics.iter().enumerate() {
+            if index != 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "error: {}", diagnostic.message)?;
+
+            let (line, column) = match (diagnostic.line, diagnostic.column) {
+                (Some(line), Some(column)) => (line, column),
+                _ => {
+                    writeln!(f, "  --> {}", self.file.display())?;
+                    continue;
+                }
+            };
+            let gutter = line.to_string().len();
+            writeln!(
+                f,
+                "{:gutter$}--> {}:{}:{}",
+                "",
+                self.file.display(),
+                line,
+                column,
+                gutter = gutter + 1
+            )?;
+            writeln!(f, "{:gutter$} |", "", gutter = gutter)?;
+            if let Some(text) = self.source.lines().nth(line - 1) {
+                writeln!(f, "{} | {}", line, text)?;
+                writeln!(
+                    f,
+                    "{:gutter$} | {:column$}{}",
+                    "",
+                    "",
+                    "^".repeat(diagnostic.width.max(1)),
+                    gutter = gutter,
+                    column = column - 1
+                )?;
+            }
+            if let Some(help) = &diagnostic.help {
+                writeln!(f, "{:gutter$} = help: {}", "", help, gutter = gutter)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[test]
+fn find_test_line_works() {
+    let source = "setup:\n  before_all: \"\"\ntest:\n  breaks:\n    test: exit 1\n  'quoted name':\n    test: exit 0\nglobal:\n  timeout: 10\n";
+
+    assert_eq!(Some(4), find_test_line(source, "breaks"));
+    assert_eq!(Some(6), find_test_line(source, "quoted name"));
+    assert_eq!(None, find_test_line(source, "timeout"));
+    assert_eq!(None, find_test_line(source, "missing"));
+    assert_eq!(Some((9, 3)), find_key(source, &["global", "timeout"]));
+}
+
+#[test]
+fn unknown_key_has_suggestion() {
+    let source = "test:\n  failing:\n    test: exit 1\n    shoud_fail: true\n";
+
+    let error = parse(source, Path::new("typo.yaml"), Format::Yaml).unwrap_err();
+
+    assert_eq!(
+        vec![Diagnostic {
+            message: String::from("unknown key `shoud_fail` in `failing`"),
+            line: Some(4),
+            column: Some(5),
+            width: 10,
+            help: Some(String::from("did you mean `should_fail`?")),
+        }],
+        error.diagnostics
+    );
+    assert_eq!(
+        "error: unknown key `shoud_fail` in `failing`\n  --> typo.yaml:4:5\n  |\n4 |     shoud_fail: true\n  |     ^^^^^^^^^^\n  = help: did you mean `should_fail`?\n",
+        error.to_string()
+    );
+}
+
+#[test]
+fn unknown_setup_key_is_reported_with_type_errors() {
+    let source =
+        "setup:\n  befor_all: exit 1\ntest:\n  slow:\n    test: sleep 1\n    timeout: soon\n";
+
+    let error = parse(source, Path::new("setup.yaml"), Format::Yaml).unwrap_err();
+
+    assert_eq!(2, error.diagnostics.len());
+    assert_eq!(
+        "unknown key `befor_all` in `setup`",
+        error.diagnostics[0].message
+    );
+    assert_eq!(
+        Some(String::from("did you mean `before_all`?")),
+        error.diagnostics[0].help
+    );
+    assert!(error.diagnostics[1].message.contains("invalid type"));
+}
+
+#[test]
+fn parse_reports_the_real_error() {
+    let source = "test:\n  slow:\n    test: sleep 1\n    timeout: soon\n";
+
+    let error = parse(source, Path::new("types.yaml"), Format::Yaml).unwrap_err();
+
+    assert_eq!(1, error.diagnostics.len());
+    assert!(error.diagnostics[0].message.contains("invalid type"));
+    assert!(error.diagnostics[0].line.is_some());
+}
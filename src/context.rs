@@ -0,0 +1,329 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Location;
+use crate::event::{Events, RunEvent};
+use crate::executor::{Containers, Executor};
+use crate::output::Stream;
+use crate::service::Services;
+use crate::shard::stable_hash;
+use crate::timing::Phase;
+
+/// receives the lines of the commands that stream their output
+pub type LineSink = Arc<dyn Fn(Stream, &str) + Send + Sync>;
+
+#[derive(Clone, Default)]
+/// runtime state that is shared between the commands of a run
+pub struct Context {
+    cancel: CancellationToken,
+    test_name: Option<String>,
+    phase: Option<Phase>,
+    sink: Option<LineSink>,
+    nocapture: bool,
+    log_dir: Option<PathBuf>,
+    location: Option<Location>,
+    cwd: Option<PathBuf>,
+    events: Events,
+    executor: Option<Arc<dyn Executor>>,
+    config_dir: Option<PathBuf>,
+    config_name: Option<String>,
+    suite_name: Option<String>,
+    containers: Containers,
+    services: Services,
+    update_snapshots: bool,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    /// where the streamed lines go, without a sink nothing is streamed
+    pub fn with_sink(mut self, sink: LineSink) -> Context {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// stream the output of every test, as if they all have `show_output: always`
+    pub fn with_nocapture(mut self, nocapture: bool) -> Context {
+        self.nocapture = nocapture;
+        self
+    }
+
+    /// write the full output of every test to this directory
+    pub fn with_log_dir<P: Into<PathBuf>>(mut self, log_dir: P) -> Context {
+        self.log_dir = Some(log_dir.into());
+        self
+    }
+
+    /// run the commands in this directory instead of the current one
+    pub fn with_cwd<P: Into<PathBuf>>(mut self, cwd: P) -> Context {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// where the events of the run are sent
+    pub fn with_events(mut self, events: Events) -> Context {
+        self.events = events;
+        self
+    }
+
+    /// run all the commands with this executor, instead of the `executor` in the settings
+    pub fn with_executor<E: Executor + 'static>(mut self, executor: E) -> Context {
+        self.executor = Some(Arc::new(executor));
+        self
+    }
+
+    /// the directory of the config, it is mounted in the containers of `image`
+    pub fn with_config_dir<P: Into<PathBuf>>(mut self, config_dir: P) -> Context {
+        self.config_dir = Some(config_dir.into());
+        self
+    }
+
+    /// the file name of the config, snapshots record it to know which config they belong to
+    pub fn with_config_name<S: Into<String>>(mut self, config_name: S) -> Context {
+        self.config_name = Some(config_name.into());
+        self
+    }
+
+    /// the prefix of the test names of the suite, see `local_test_name`
+    pub fn with_suite_name<S: Into<String>>(mut self, suite_name: S) -> Context {
+        self.suite_name = Some(suite_name.into());
+        self
+    }
+
+    /// write the output of the tests with a `snapshot` to their snapshot, instead of
+    /// comparing them
+    pub fn with_update_snapshots(mut self, update_snapshots: bool) -> Context {
+        self.update_snapshots = update_snapshots;
+        self
+    }
+
+    /// the containers that are shared by the tests with `reuse_container`
+    pub fn with_containers(mut self, containers: Containers) -> Context {
+        self.containers = containers;
+        self
+    }
+
+    /// the services of the config, their logs are added to the errors of the tests
+    pub fn with_services(mut self, services: Services) -> Context {
+        self.services = services;
+        self
+    }
+
+    /// a copy of this context for a single test, streamed lines get prefixed with the name
+    pub fn for_test(&self, test_name: &str) -> Context {
+        let mut ctx = self.clone();
+        ctx.test_name = Some(test_name.to_string());
+        ctx
+    }
+
+    /// where the test is defined, used in error messages
+    pub fn with_location(mut self, location: Location) -> Context {
+        self.location = Some(location);
+        self
+    }
+
+    /// a copy of this context for one of the phases of the test
+    pub fn for_phase(&self, phase: Phase) -> Context {
+        let mut ctx = self.clone();
+        ctx.phase = Some(phase);
+        ctx
+    }
+
+    /// a copy of this context that is not cancelled with this one, for cleaning up
+    pub fn uncancellable(&self) -> Context {
+        let mut ctx = self.clone();
+        ctx.cancel = CancellationToken::new();
+        ctx
+    }
+
+    pub fn test_name(&self) -> Option<&str> {
+        self.test_name.as_deref()
+    }
+
+    pub fn phase(&self) -> Option<Phase> {
+        self.phase
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    pub fn cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    /// file for the full output: `<log_dir>/<test>.stdout`, or `<test>.before.stdout` for
+    /// the before phase. Only tests get a log file
+    pub fn log_path(&self, stream: Stream) -> Option<PathBuf> {
+        let log_dir = self.log_dir.as_ref()?;
+        let test_name = unique_file_name(self.test_name.as_ref()?);
+
+        let file_name = match self.phase {
+            None | Some(Phase::Test) => format!("{}.{}", test_name, stream.extension()),
+            Some(phase) => format!("{}.{}.{}", test_name, phase, stream.extension()),
+        };
+        Some(log_dir.join(file_name))
+    }
+
+    pub fn executor(&self) -> Option<&dyn Executor> {
+        self.executor.as_deref()
+    }
+
+    pub fn config_dir(&self) -> Option<&Path> {
+        self.config_dir.as_deref()
+    }
+
+    pub fn config_name(&self) -> Option<&str> {
+        self.config_name.as_deref()
+    }
+
+    /// the path of the config, the tests of a config share the containers of `reuse_container`
+    pub fn group(&self) -> PathBuf {
+        let config_dir = self.config_dir.clone().unwrap_or_default();
+        match &self.config_name {
+            Some(config_name) => config_dir.join(config_name),
+            None => config_dir,
+        }
+    }
+
+    /// the name of the test in its config, without the suite name
+    pub fn local_test_name(&self) -> Option<&str> {
+        let test_name = self.test_name.as_deref()?;
+        Some(
+            self.suite_name
+                .as_ref()
+                .and_then(|name| test_name.strip_prefix(name.as_str()))
+                .and_then(|test_name| test_name.strip_prefix("::"))
+                .unwrap_or(test_name),
+        )
+    }
+
+    pub fn update_snapshots(&self) -> bool {
+        self.update_snapshots
+    }
+
+    pub fn containers(&self) -> &Containers {
+        &self.containers
+    }
+
+    pub fn services(&self) -> &Services {
+        &self.services
+    }
+
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    pub fn emit(&self, event: RunEvent) {
+        self.events.emit(event)
+    }
+
+    /// sends `PhaseStarted` for the phase and test of this context
+    pub fn phase_started(&self) {
+        if let Some(phase) = self.phase {
+            self.emit(RunEvent::PhaseStarted {
+                test_name: self.test_name.clone(),
+                phase,
+            });
+        }
+    }
+
+    pub fn nocapture(&self) -> bool {
+        self.nocapture
+    }
+
+    pub fn print_line(&self, stream: Stream, line: &str) {
+        if let Some(sink) = &self.sink {
+            match &self.test_name {
+                Some(test_name) => sink(stream, &format!("[{}] {}", test_name, line)),
+                None => sink(stream, line),
+            }
+        }
+    }
+
+    /// stop all the commands started with this context, running processes are killed
+    pub fn cancel(&self) {
+        self.cancel.cancel()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await
+    }
+}
+
+/// waits for Ctrl-C, or SIGTERM on unix, the signals that should stop a run
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => (),
+                _ = terminate.recv() => (),
+            }
+            return;
+        }
+    }
+    // without a signal handler the run can not be stopped this way
+    if tokio::signal::ctrl_c().await.is_err() {
+        futures::future::pending::<()>().await;
+    }
+}
+
+impl std::fmt::Debug for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Context")
+            .field("cancel", &self.cancel)
+            .field("test_name", &self.test_name)
+            .field("phase", &self.phase)
+            .field("nocapture", &self.nocapture)
+            .field("log_dir", &self.log_dir)
+            .field("location", &self.location)
+            .field("cwd", &self.cwd)
+            .field("events", &self.events)
+            .field("executor", &self.executor.is_some())
+            .field("config_dir", &self.config_dir)
+            .field("config_name", &self.config_name)
+            .field("suite_name", &self.suite_name)
+            .field("update_snapshots", &self.update_snapshots)
+            .finish()
+    }
+}
+
+/// the name with everything but letters, digits, `-` and `_` replaced by `_`, to use in a
+/// file name
+pub(crate) fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// like `file_name`, but when something is replaced a hash of the name is added, so `a b`
+/// and `a_b` do not end up in the same file
+pub(crate) fn unique_file_name(name: &str) -> String {
+    let replaced = file_name(name);
+    if replaced == name {
+        replaced
+    } else {
+        format!("{}-{:08x}", replaced, stable_hash(name) as u32)
+    }
+}
+
+#[test]
+fn file_names_are_unique() {
+    assert_eq!("a_b", unique_file_name("a_b"));
+    assert!(unique_file_name("a b").starts_with("a_b-"));
+    assert_ne!(unique_file_name("a b"), unique_file_name("a/b"));
+}
@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+/// extensions a discovered config can have
+const EXTENSIONS: &[&str] = &["yaml", "yml", "toml", "json"];
+
+/// finds every `baret.yaml` and `*.baret.yaml` below `root`, or with one of the other config
+/// extensions. Files ignored by a `.gitignore` and hidden directories are skipped
+pub fn discover(root: &Path) -> Result<Vec<PathBuf>, ignore::Error> {
+    let mut configs = Vec::new();
+    for entry in WalkBuilder::new(root).require_git(false).build() {
+        let entry = entry?;
+        let is_file = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file());
+        if is_file && is_config(entry.path()) {
+            configs.push(entry.into_path());
+        }
+    }
+
+    configs.sort();
+    Ok(configs)
+}
+
+fn is_config(path: &Path) -> bool {
+    let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+
+    EXTENSIONS.iter().any(|extension| {
+        let config = format!("baret.{}", extension);
+        file_name == config || file_name.ends_with(&format!(".{}", config))
+    })
+}
+
+/// the name of a discovered suite: its path relative to `root` without the extension, for
+/// example `api/login` for `api/login.baret.yaml`
+pub fn suite_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let name = relative.to_string_lossy().replace('\\', "/");
+    let name = name.trim_start_matches("./");
+
+    EXTENSIONS
+        .iter()
+        .find_map(|extension| {
+            name.strip_suffix(&format!(".baret.{}", extension))
+                .or_else(|| name.strip_suffix(&format!(".{}", extension)))
+        })
+        .unwrap_or(name)
+        .to_string()
+}
+
+#[test]
+fn discover_finds_configs() {
+    let root = tempfile::tempdir().unwrap();
+    let files = [
+        "baret.yaml",
+        "api/login.baret.yaml",
+        "api/notes.yaml",
+        "web/baret.toml",
+        "ignored/baret.yaml",
+        ".hidden/baret.yaml",
+    ];
+    for file in files.iter() {
+        let path = root.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "test: {}\n").unwrap();
+    }
+    std::fs::write(root.path().join(".gitignore"), "/ignored\n").unwrap();
+
+    let configs: Vec<String> = discover(root.path())
+        .unwrap()
+        .iter()
+        .map(|path| suite_name(root.path(), path))
+        .collect();
+
+    assert_eq!(vec!["api/login", "baret", "web/baret"], configs);
+}
@@ -1,14 +1,152 @@
 use derive_more::From;
 use glob::{GlobError, PatternError};
-use std::process::Output;
+use std::path::PathBuf;
 use tokio::io;
 
+use crate::assertion::AssertionFailure;
+use crate::config::Location;
+use crate::interactive::StepFailure;
+use crate::output::{Captured, CommandOutput};
+use crate::service::ServiceFailure;
+use crate::snapshot::SnapshotFailure;
+use crate::timing::Phase;
+
 pub type Result = std::result::Result<(), Error>;
 
 #[derive(Debug, From)]
 pub enum Error {
     IO(io::Error),
-    ExitCode(Output),
+    ExitCode(CommandOutput),
+    /// a step of an interactive test failed
+    Step(StepFailure),
+    /// the output does not match the snapshot of the test
+    Snapshot(SnapshotFailure),
+    /// a file is not what an assertion of the test expects
+    Assertion(AssertionFailure),
+    /// a service did not start or did not get ready
+    Service(ServiceFailure),
     PatternError(PatternError),
     GlobError(GlobError),
+    /// the run was stopped before the command finished
+    #[from(ignore)]
+    Cancelled,
+    /// error with information about where it happened
+    #[from(ignore)]
+    Context(Box<ErrorContext>, Box<Error>),
+    /// more than one phase failed, for example the test and its `after`
+    #[from(ignore)]
+    Many(Vec<Error>),
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+/// where an error happened, everything is optional because not every error has all of it
+pub struct ErrorContext {
+    pub test_name: Option<String>,
+    pub phase: Option<Phase>,
+    /// the program with its arguments
+    pub command: Option<Vec<String>>,
+    /// directory the command ran in
+    pub cwd: Option<PathBuf>,
+    /// where the test is defined
+    pub location: Option<Location>,
+    /// file the error is about, for example a group file that can not be read
+    pub path: Option<PathBuf>,
+    /// the end of the logs of the services that ran next to the test
+    pub services: Vec<(String, Captured)>,
+}
+
+impl Error {
+    /// adds context to the error, context that is already there is overwritten
+    pub fn with_context<F: FnOnce(&mut ErrorContext)>(self, add_context: F) -> Error {
+        match self {
+            Error::Cancelled => Error::Cancelled,
+            Error::Context(mut context, error) => {
+                add_context(&mut context);
+                Error::Context(context, error)
+            }
+            error => {
+                let mut context = ErrorContext::default();
+                add_context(&mut context);
+                Error::Context(Box::new(context), Box::new(error))
+            }
+        }
+    }
+
+    pub fn in_phase(self, phase: Phase) -> Error {
+        self.with_context(|context| context.phase = Some(phase))
+    }
+
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Context(context, _) => Some(context),
+            _ => None,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        match self {
+            Error::Cancelled => true,
+            Error::Context(_, error) => error.is_cancelled(),
+            Error::Many(errors) => errors.iter().any(Error::is_cancelled),
+            _ => false,
+        }
+    }
+
+    /// the phase that failed first
+    pub fn phase(&self) -> Option<Phase> {
+        match self {
+            Error::Context(context, error) => context.phase.or_else(|| error.phase()),
+            Error::Many(errors) => errors.iter().find_map(Error::phase),
+            _ => None,
+        }
+    }
+
+    /// the output of the command that failed first
+    pub fn output(&self) -> Option<&CommandOutput> {
+        match self {
+            Error::ExitCode(output) => Some(output),
+            Error::Context(_, error) => error.output(),
+            Error::Many(errors) => errors.iter().find_map(Error::output),
+            _ => None,
+        }
+    }
+
+    pub fn from_many(mut errors: Vec<Error>) -> Result {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Error::Many(errors)),
+        }
+    }
+}
+
+/// the phase goes above the error, the rest of the context below it
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            writeln!(f, "path: {}", path.display())?;
+        }
+        if let Some(command) = &self.command {
+            writeln!(
+                f,
+                "command: {}",
+                shlex::join(command.iter().map(String::as_str))
+            )?;
+        }
+        if let Some(cwd) = &self.cwd {
+            writeln!(f, "cwd: {}", cwd.display())?;
+        }
+        match (&self.test_name, &self.location) {
+            (Some(test_name), Some(location)) => {
+                writeln!(f, "defined: test '{}' in {}", test_name, location)
+            }
+            (Some(test_name), None) => writeln!(f, "defined: test '{}'", test_name),
+            (None, Some(location)) => writeln!(f, "defined: {}", location),
+            (None, None) => Ok(()),
+        }?;
+        for (name, log) in &self.services {
+            writeln!(f, "service `{}` log:\n{}", name, log)?;
+        }
+        Ok(())
+    }
 }
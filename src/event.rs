@@ -0,0 +1,212 @@
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::output::Stream;
+use crate::timing::{Phase, Timings};
+
+#[derive(Debug, PartialEq, Clone)]
+/// what happens during a run, see `Runner::events`
+pub enum RunEvent {
+    /// the test is selected and waits for its turn
+    TestQueued {
+        test_name: String,
+    },
+    TestStarted {
+        test_name: String,
+    },
+    /// a script started, there is no test name for `before_all` and `after_all`
+    PhaseStarted {
+        test_name: Option<String>,
+        phase: Phase,
+    },
+    /// output of a script, as it was read
+    OutputChunk {
+        test_name: Option<String>,
+        phase: Option<Phase>,
+        stream: Stream,
+        data: Vec<u8>,
+    },
+    /// this many `OutputChunk`s were dropped because the subscriber did not keep up
+    OutputLagged {
+        missed: usize,
+    },
+    /// also sent for the tests that did not run
+    TestFinished {
+        test_name: String,
+        status: Status,
+        timings: Timings,
+    },
+    /// the tests and the setup of a suite are done, there is no suite name when the run has
+    /// only one suite
+    SuiteFinished {
+        suite: Option<String>,
+        passed: usize,
+        failed: usize,
+        not_run: usize,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// the outcome of a test, without the error
+pub enum Status {
+    Passed,
+    Failed,
+    Cancelled,
+    NotRun,
+}
+
+/// the amount of `OutputChunk`s a subscriber can fall behind before chunks are dropped
+pub const OUTPUT_BACKLOG: usize = 1024;
+
+#[derive(Clone, Default)]
+/// sends the events of a run to every subscriber. A subscriber never misses the other events, the
+/// `OutputChunk`s it does not read in time are replaced by an `OutputLagged`
+pub struct Events {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+struct Subscriber {
+    sender: UnboundedSender<RunEvent>,
+    output: bool,
+    /// chunks sent but not read yet
+    backlog: Arc<AtomicUsize>,
+    /// chunks dropped since the last event that was sent
+    missed: usize,
+}
+
+impl Subscriber {
+    /// false when the subscriber is gone
+    fn send(&mut self, event: &RunEvent) -> bool {
+        if let RunEvent::OutputChunk { .. } = event {
+            if !self.output {
+                return true;
+            }
+            if self.backlog.load(Ordering::SeqCst) >= OUTPUT_BACKLOG {
+                self.missed += 1;
+                return true;
+            }
+            self.backlog.fetch_add(1, Ordering::SeqCst);
+        }
+        if self.missed > 0 {
+            let missed = std::mem::take(&mut self.missed);
+            if self.sender.send(RunEvent::OutputLagged { missed }).is_err() {
+                return false;
+            }
+        }
+        self.sender.send(event.clone()).is_ok()
+    }
+}
+
+/// the events of a run, see `Events::subscribe`
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: UnboundedReceiverStream<RunEvent>,
+    backlog: Arc<AtomicUsize>,
+}
+
+impl tokio_stream::Stream for EventStream {
+    type Item = RunEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<RunEvent>> {
+        let event = tokio_stream::Stream::poll_next(Pin::new(&mut self.receiver), cx);
+        if let Poll::Ready(Some(RunEvent::OutputChunk { .. })) = event {
+            self.backlog.fetch_sub(1, Ordering::SeqCst);
+        }
+        event
+    }
+}
+
+impl Events {
+    /// the events sent from now on, the stream ends when the run is done
+    pub fn subscribe(&self) -> EventStream {
+        self.add(true)
+    }
+
+    /// the events sent from now on without the `OutputChunk`s, for subscribers that have no use
+    /// for the output
+    pub fn subscribe_without_output(&self) -> EventStream {
+        self.add(false)
+    }
+
+    /// whether a subscriber wants the `OutputChunk`s
+    pub fn wants_output(&self) -> bool {
+        self.lock().iter().any(|subscriber| subscriber.output)
+    }
+
+    pub fn emit(&self, event: RunEvent) {
+        // subscribers that are gone are dropped
+        self.lock().retain_mut(|subscriber| subscriber.send(&event));
+    }
+
+    /// ends the streams of the subscribers
+    pub(crate) fn close(&self) {
+        self.lock().clear();
+    }
+
+    fn add(&self, output: bool) -> EventStream {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let backlog = Arc::new(AtomicUsize::new(0));
+        self.lock().push(Subscriber {
+            sender,
+            output,
+            backlog: backlog.clone(),
+            missed: 0,
+        });
+        EventStream {
+            receiver: UnboundedReceiverStream::new(receiver),
+            backlog,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Subscriber>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for Events {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Events")
+            .field("subscribers", &self.lock().len())
+            .finish()
+    }
+}
+
+#[test]
+fn slow_subscriber_misses_output() {
+    use tokio_stream::StreamExt;
+
+    let events = Events::default();
+    let stream = events.subscribe();
+    let chunk = RunEvent::OutputChunk {
+        test_name: None,
+        phase: None,
+        stream: Stream::Stdout,
+        data: b"output".to_vec(),
+    };
+    for _ in 0..OUTPUT_BACKLOG + 2 {
+        events.emit(chunk.clone());
+    }
+    let queued = RunEvent::TestQueued {
+        test_name: String::from("test"),
+    };
+    events.emit(queued.clone());
+    events.close();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let received: Vec<RunEvent> = runtime.block_on(stream.collect());
+    assert_eq!(received.len(), OUTPUT_BACKLOG + 2);
+    assert!(received[..OUTPUT_BACKLOG]
+        .iter()
+        .all(|event| event == &chunk));
+    assert_eq!(
+        received[OUTPUT_BACKLOG..],
+        [RunEvent::OutputLagged { missed: 2 }, queued]
+    );
+}
@@ -0,0 +1,754 @@
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{self, AsyncRead, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+/// a command that is ready to run, the settings are already applied
+#[derive(Debug, PartialEq, Clone)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    /// the variables from the `env` settings, on top of the environment of baret
+    pub env: Vec<(String, String)>,
+    /// start from an empty environment instead of the one of baret
+    pub clear_env: bool,
+    pub cwd: Option<PathBuf>,
+    /// written to the stdin of the command, without it stdin is empty
+    pub stdin: Option<Vec<u8>>,
+}
+
+/// a started command, dropping `status` stops it
+pub struct Process {
+    pub stdout: Box<dyn AsyncRead + Send + Unpin>,
+    pub stderr: Box<dyn AsyncRead + Send + Unpin>,
+    pub status: BoxFuture<'static, io::Result<ExitStatus>>,
+}
+
+/// starts the commands of the tests, the default runs them on this machine
+pub trait Executor: Send + Sync {
+    fn spawn(&self, invocation: Invocation) -> BoxFuture<'static, io::Result<Process>>;
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+/// where the commands run
+pub enum ExecutorConfig {
+    /// on this machine
+    #[default]
+    Local,
+    /// in a running container with `docker exec`, the value is the name of the container
+    Docker(String),
+    /// on another machine with `ssh`, the value is the host, for example `user@host`
+    Ssh(String),
+    /// nothing runs, every command returns this output
+    Mock(MockOutput),
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
+/// the output of the mock executor
+pub struct MockOutput {
+    #[serde(default)]
+    pub exit_code: i32,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+}
+
+impl ExecutorConfig {
+    pub fn executor(&self) -> Box<dyn Executor> {
+        match self {
+            ExecutorConfig::Local => Box::new(Local),
+            ExecutorConfig::Docker(container) => Box::new(Docker {
+                container: container.clone(),
+            }),
+            ExecutorConfig::Ssh(host) => Box::new(Ssh { host: host.clone() }),
+            ExecutorConfig::Mock(output) => Box::new(Mock {
+                output: output.clone(),
+            }),
+        }
+    }
+
+    /// the commands run on this machine, so their programs can be checked
+    pub fn is_local(&self) -> bool {
+        matches!(self, ExecutorConfig::Local)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// spawns the command as a child process of baret, in a process group of its own. When it is
+/// stopped the processes it started are stopped too
+pub struct Local;
+
+impl Executor for Local {
+    fn spawn(&self, invocation: Invocation) -> BoxFuture<'static, io::Result<Process>> {
+        let stdin = invocation.stdin;
+        let mut command = std::process::Command::new(&invocation.program);
+        if invocation.clear_env {
+            command.env_clear();
+        }
+        command.envs(invocation.env);
+        command.args(&invocation.args);
+        if let Some(cwd) = invocation.cwd {
+            command.current_dir(cwd);
+        }
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let command = Command::from(command);
+
+        Box::pin(async move {
+            let (mut process, pid) = spawn_child(command, stdin)?;
+            let kill = KillGroupOnDrop { pid };
+            let status = process.status;
+            process.status = Box::pin(async move {
+                let status = status.await;
+                kill.disarm();
+                status
+            });
+            Ok(process)
+        })
+    }
+}
+
+/// kills the process group of a local command that is dropped before it ended, for example
+/// when it timed out or the run stopped
+struct KillGroupOnDrop {
+    pid: Option<u32>,
+}
+
+impl KillGroupOnDrop {
+    fn disarm(mut self) {
+        self.pid = None;
+    }
+}
+
+impl Drop for KillGroupOnDrop {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid.take() {
+            // SAFETY: kill only sends a signal, the group is the one of the command
+            unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// runs the command in a running container, the env is passed with `-e`. The directory is
+/// only used when it is absolute, a relative one means nothing inside the container
+pub struct Docker {
+    pub container: String,
+}
+
+impl Executor for Docker {
+    fn spawn(&self, invocation: Invocation) -> BoxFuture<'static, io::Result<Process>> {
+        let mut command = Command::new("docker");
+        command.arg("exec");
+        if invocation.stdin.is_some() {
+            command.arg("-i");
+        }
+        if let Some(cwd) = invocation.cwd.as_ref().filter(|cwd| cwd.is_absolute()) {
+            command.arg("-w").arg(cwd);
+        }
+        if invocation.clear_env {
+            command.arg(&self.container).arg("env").arg("-i");
+            command.args(
+                invocation
+                    .env
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value)),
+            );
+        } else {
+            for (key, value) in &invocation.env {
+                command.arg("-e").arg(format!("{}={}", key, value));
+            }
+            command.arg(&self.container);
+        }
+        command.arg(&invocation.program).args(&invocation.args);
+        let stdin = invocation.stdin;
+
+        Box::pin(async move { spawn(command, stdin) })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// runs the command in a new container of `image` with docker or podman. The config directory
+/// and the working directory are mounted at the same path, the container is removed when the
+/// command stops, also when it is killed
+pub struct Container {
+    /// `docker` or `podman`
+    pub runtime: String,
+    pub image: String,
+    /// directories to mount next to the working directory
+    pub mounts: Vec<PathBuf>,
+    /// run all the commands in one container instead, see `Containers`
+    pub shared: Option<Containers>,
+    /// the config of the command, every config gets its own shared containers
+    pub group: PathBuf,
+}
+
+impl Executor for Container {
+    fn spawn(&self, invocation: Invocation) -> BoxFuture<'static, io::Result<Process>> {
+        let container = self.clone();
+        Box::pin(async move {
+            let current_dir = std::env::current_dir()?;
+            let cwd = match &invocation.cwd {
+                Some(cwd) => current_dir.join(cwd),
+                None => current_dir.clone(),
+            };
+            let mut mounts: Vec<PathBuf> = container
+                .mounts
+                .iter()
+                .map(|mount| current_dir.join(mount))
+                .collect();
+            mounts.push(cwd.clone());
+            let (options, program) = container_args(&invocation);
+
+            let mut command = Command::new(&container.runtime);
+            match &container.shared {
+                Some(containers) => {
+                    let name = containers
+                        .start(
+                            &container.group,
+                            &container.runtime,
+                            &container.image,
+                            &mounts,
+                        )
+                        .await?;
+                    command.args(exec_args(&name, &cwd, options, program));
+                    spawn(command, invocation.stdin)
+                }
+                None => {
+                    let name = container_name();
+                    command.args(run_args(
+                        &name,
+                        &container.image,
+                        &mounts,
+                        &cwd,
+                        options,
+                        program,
+                    ));
+                    let remove = RemoveOnDrop {
+                        runtime: container.runtime.clone(),
+                        name: Some(name),
+                    };
+                    let mut process = spawn(command, invocation.stdin)?;
+                    let status = process.status;
+                    process.status = Box::pin(async move {
+                        let status = status.await;
+                        // `--rm` removes the container when the command ends by itself
+                        remove.disarm();
+                        status
+                    });
+                    Ok(process)
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// the containers started for `reuse_container`, one per config and image. They keep running
+/// until `remove_all`
+pub struct Containers {
+    started: Arc<Mutex<Vec<SharedContainer>>>,
+}
+
+#[derive(Debug)]
+struct SharedContainer {
+    group: PathBuf,
+    runtime: String,
+    image: String,
+    name: String,
+}
+
+impl Containers {
+    /// the name of the running container of `image` for the config `group`, it is started
+    /// when there is none yet
+    async fn start(
+        &self,
+        group: &Path,
+        runtime: &str,
+        image: &str,
+        mounts: &[PathBuf],
+    ) -> io::Result<String> {
+        let mut started = self.started.lock().await;
+        let running = started.iter().find(|container| {
+            container.group == group && container.runtime == runtime && container.image == image
+        });
+        if let Some(container) = running {
+            return Ok(container.name.clone());
+        }
+
+        let name = container_name();
+        let output = Command::new(runtime)
+            .args(detached_run_args(&name, image, mounts))
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "unable to start a container of `{}`: {}",
+                image,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        started.push(SharedContainer {
+            group: group.to_path_buf(),
+            runtime: runtime.to_string(),
+            image: image.to_string(),
+            name: name.clone(),
+        });
+        Ok(name)
+    }
+
+    pub async fn remove_all(&self) {
+        let started = std::mem::take(&mut *self.started.lock().await);
+        for container in started {
+            let _ = Command::new(container.runtime)
+                .args(rm_args(&container.name))
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await;
+        }
+    }
+}
+
+/// removes the container when the command is dropped before it ended, for example when the
+/// run is stopped
+struct RemoveOnDrop {
+    runtime: String,
+    name: Option<String>,
+}
+
+impl RemoveOnDrop {
+    fn disarm(mut self) {
+        self.name = None;
+    }
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        if let Some(name) = self.name.take() {
+            // not waited for, the runtime of baret may be shutting down
+            let _ = std::process::Command::new(&self.runtime)
+                .args(rm_args(&name))
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+        }
+    }
+}
+
+/// `docker` when it is installed, otherwise `podman`
+pub fn container_runtime() -> Option<&'static str> {
+    let path = std::env::var_os("PATH")?;
+    ["docker", "podman"].iter().copied().find(|runtime| {
+        std::env::split_paths(&path).any(|directory| directory.join(runtime).is_file())
+    })
+}
+
+fn container_name() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "baret-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// `run` for a container of `image` that is removed when the program stops
+fn run_args(
+    name: &str,
+    image: &str,
+    mounts: &[PathBuf],
+    cwd: &Path,
+    options: Vec<String>,
+    program: Vec<String>,
+) -> Vec<String> {
+    let mut args = vec![
+        String::from("run"),
+        String::from("--rm"),
+        String::from("--name"),
+        name.to_string(),
+    ];
+    args.extend(volume_args(mounts));
+    args.push(String::from("-w"));
+    args.push(cwd.display().to_string());
+    args.extend(options);
+    args.push(image.to_string());
+    args.extend(program);
+    args
+}
+
+/// `run` for a shared container of `image`, it sleeps until it is removed
+fn detached_run_args(name: &str, image: &str, mounts: &[PathBuf]) -> Vec<String> {
+    let mut args = vec![
+        String::from("run"),
+        String::from("--detach"),
+        String::from("--rm"),
+        String::from("--name"),
+        name.to_string(),
+    ];
+    args.extend(volume_args(mounts));
+    args.extend(
+        ["--entrypoint", "sleep", image, "2147483647"]
+            .iter()
+            .map(|arg| arg.to_string()),
+    );
+    args
+}
+
+/// `exec` of the program in the shared container `name`
+fn exec_args(name: &str, cwd: &Path, options: Vec<String>, program: Vec<String>) -> Vec<String> {
+    let mut args = vec![
+        String::from("exec"),
+        String::from("-w"),
+        cwd.display().to_string(),
+    ];
+    args.extend(options);
+    args.push(name.to_string());
+    args.extend(program);
+    args
+}
+
+fn rm_args(name: &str) -> Vec<String> {
+    vec![
+        String::from("rm"),
+        String::from("--force"),
+        name.to_string(),
+    ]
+}
+
+/// the directories mounted at the same path, leaving out the ones inside another one
+fn volume_args(mounts: &[PathBuf]) -> Vec<String> {
+    let mut mounts: Vec<&Path> = mounts.iter().map(PathBuf::as_path).collect();
+    mounts.sort();
+    mounts.dedup();
+    let mut args = Vec::new();
+    let mut mounted: Vec<&Path> = Vec::new();
+    for mount in mounts {
+        if mounted.iter().any(|parent| mount.starts_with(parent)) {
+            continue;
+        }
+        mounted.push(mount);
+        args.push(String::from("--volume"));
+        args.push(format!("{0}:{0}", mount.display()));
+    }
+    args
+}
+
+/// the options that pass the env and the stdin to `docker run` or `docker exec`, and the
+/// program with its arguments. With `clear_env` the program runs with `env -i` instead
+fn container_args(invocation: &Invocation) -> (Vec<String>, Vec<String>) {
+    let variables = invocation
+        .env
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value));
+    let mut options = Vec::new();
+    if invocation.stdin.is_some() {
+        options.push(String::from("--interactive"));
+    }
+    let mut program = Vec::new();
+    if invocation.clear_env {
+        program.push(String::from("env"));
+        program.push(String::from("-i"));
+        program.extend(variables);
+    } else {
+        for variable in variables {
+            options.push(String::from("--env"));
+            options.push(variable);
+        }
+    }
+    program.push(invocation.program.clone());
+    program.extend(invocation.args.iter().cloned());
+    (options, program)
+}
+
+#[derive(Debug, Clone)]
+/// runs the command on another machine, ssh has to log in without asking anything. The
+/// directory is only used when it is absolute, like with `Docker`
+pub struct Ssh {
+    pub host: String,
+}
+
+impl Ssh {
+    /// the command line for the remote shell
+    fn remote_command(invocation: &Invocation) -> String {
+        let mut words = Vec::new();
+        if let Some(cwd) = invocation.cwd.as_ref().filter(|cwd| cwd.is_absolute()) {
+            words.push(format!("cd {} &&", quote(&cwd.to_string_lossy())));
+        }
+        words.push(String::from("env"));
+        if invocation.clear_env {
+            words.push(String::from("-i"));
+        }
+        for (key, value) in &invocation.env {
+            words.push(quote(&format!("{}={}", key, value)));
+        }
+        words.push(quote(&invocation.program));
+        words.extend(invocation.args.iter().map(|arg| quote(arg)));
+        words.join(" ")
+    }
+}
+
+impl Executor for Ssh {
+    fn spawn(&self, invocation: Invocation) -> BoxFuture<'static, io::Result<Process>> {
+        let mut command = Command::new("ssh");
+        command
+            .arg("-T")
+            .arg("-o")
+            .arg("BatchMode=yes")
+            .arg(&self.host)
+            .arg("--")
+            .arg(Ssh::remote_command(&invocation));
+
+        Box::pin(async move { spawn(command, invocation.stdin) })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// runs nothing, for testing configs
+pub struct Mock {
+    pub output: MockOutput,
+}
+
+impl Executor for Mock {
+    fn spawn(&self, _invocation: Invocation) -> BoxFuture<'static, io::Result<Process>> {
+        let output = self.output.clone();
+        Box::pin(async move {
+            let status = exit_status(output.exit_code);
+            Ok(Process {
+                stdout: Box::new(std::io::Cursor::new(output.stdout.into_bytes())),
+                stderr: Box::new(std::io::Cursor::new(output.stderr.into_bytes())),
+                status: Box::pin(async move { Ok(status) }),
+            })
+        })
+    }
+}
+
+/// spawns the command with piped output, stdin is `/dev/null` unless there is input for it.
+/// Dropping the process kills the local command only, for `Ssh` and `Docker` that is the ssh or
+/// docker client and the command on the other side can keep running
+fn spawn(command: Command, input: Option<Vec<u8>>) -> io::Result<Process> {
+    spawn_child(command, input).map(|(process, _)| process)
+}
+
+/// like `spawn`, also returns the id of the process
+fn spawn_child(mut command: Command, input: Option<Vec<u8>>) -> io::Result<(Process, Option<u32>)> {
+    command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = command.spawn()?;
+    let pid = child.id();
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stdin = child.stdin.take();
+    let write = async move {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            // the command does not have to read all of it, so a closed pipe is fine
+            match stdin.write_all(&input).await {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+                _ => (),
+            }
+        }
+        Ok(())
+    };
+    let process = Process {
+        stdout: Box::new(stdout),
+        stderr: Box::new(stderr),
+        status: Box::pin(async move {
+            let (written, status) = tokio::join!(write, child.wait());
+            written?;
+            status
+        }),
+    };
+    Ok((process, pid))
+}
+
+#[cfg(unix)]
+pub(crate) fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+
+    ExitStatus::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(windows)]
+pub(crate) fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+
+    ExitStatus::from_raw(code as u32)
+}
+
+/// quotes a word for `sh`
+fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@".contains(c))
+    {
+        return word.to_string();
+    }
+
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+#[test]
+fn ssh_quotes_the_remote_command() {
+    let invocation = Invocation {
+        program: String::from("sh"),
+        args: vec![String::from("-c"), String::from("echo 'hi' $NAME")],
+        env: vec![(String::from("NAME"), String::from("a b"))],
+        clear_env: false,
+        cwd: Some(PathBuf::from("/srv/app")),
+        stdin: None,
+    };
+
+    assert_eq!(
+        "cd /srv/app && env 'NAME=a b' sh -c 'echo '\\''hi'\\'' $NAME'",
+        Ssh::remote_command(&invocation)
+    );
+}
+
+#[test]
+fn ssh_skips_a_relative_directory() {
+    let invocation = Invocation {
+        program: String::from("true"),
+        args: Vec::new(),
+        env: Vec::new(),
+        clear_env: true,
+        cwd: Some(PathBuf::from("tests/test_data")),
+        stdin: None,
+    };
+
+    assert_eq!("env -i true", Ssh::remote_command(&invocation));
+}
+
+#[test]
+fn container_args_pass_the_env_and_mounts() {
+    let mut invocation = Invocation {
+        program: String::from("sh"),
+        args: vec![String::from("-c"), String::from("echo $NAME")],
+        env: vec![(String::from("NAME"), String::from("baret"))],
+        clear_env: false,
+        cwd: None,
+        stdin: None,
+    };
+
+    assert_eq!(
+        (
+            vec![String::from("--env"), String::from("NAME=baret")],
+            vec![
+                String::from("sh"),
+                String::from("-c"),
+                String::from("echo $NAME")
+            ]
+        ),
+        container_args(&invocation)
+    );
+
+    invocation.clear_env = true;
+    let (options, program) = container_args(&invocation);
+    assert!(options.is_empty());
+    assert_eq!(vec!["env", "-i", "NAME=baret", "sh"], program[..4].to_vec());
+
+    invocation.stdin = Some(b"hallo".to_vec());
+    assert_eq!(vec!["--interactive"], container_args(&invocation).0);
+
+    let mounts = [
+        PathBuf::from("/srv/app/tests"),
+        PathBuf::from("/srv/app"),
+        PathBuf::from("/tmp"),
+    ];
+    assert_eq!(
+        vec!["--volume", "/srv/app:/srv/app", "--volume", "/tmp:/tmp"],
+        volume_args(&mounts)
+    );
+}
+
+#[test]
+fn container_command_lines() {
+    let mounts = [PathBuf::from("/srv/app")];
+    let cwd = Path::new("/srv/app/tests");
+    let options = vec![String::from("--env"), String::from("NAME=baret")];
+    let program = vec![
+        String::from("sh"),
+        String::from("-c"),
+        String::from("exit 0"),
+    ];
+
+    assert_eq!(
+        vec![
+            "run",
+            "--rm",
+            "--name",
+            "baret-1-0",
+            "--volume",
+            "/srv/app:/srv/app",
+            "-w",
+            "/srv/app/tests",
+            "--env",
+            "NAME=baret",
+            "alpine:3.18",
+            "sh",
+            "-c",
+            "exit 0"
+        ],
+        run_args(
+            "baret-1-0",
+            "alpine:3.18",
+            &mounts,
+            cwd,
+            options.clone(),
+            program.clone()
+        )
+    );
+    assert_eq!(
+        vec![
+            "run",
+            "--detach",
+            "--rm",
+            "--name",
+            "baret-1-1",
+            "--volume",
+            "/srv/app:/srv/app",
+            "--entrypoint",
+            "sleep",
+            "alpine:3.18",
+            "2147483647"
+        ],
+        detached_run_args("baret-1-1", "alpine:3.18", &mounts)
+    );
+    assert_eq!(
+        vec![
+            "exec",
+            "-w",
+            "/srv/app/tests",
+            "--env",
+            "NAME=baret",
+            "baret-1-1",
+            "sh",
+            "-c",
+            "exit 0"
+        ],
+        exec_args("baret-1-1", cwd, options, program)
+    );
+    assert_eq!(vec!["rm", "--force", "baret-1-1"], rm_args("baret-1-1"));
+}
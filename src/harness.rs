@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use libtest_mimic::{Arguments, Failed, Trial};
+use tokio::runtime::Runtime;
+
+use crate::command;
+use crate::config::Format;
+use crate::context::shutdown_signal;
+use crate::executor::Containers;
+use crate::service::Services;
+use crate::suite::{name_suites, Suite};
+use crate::{Context, Tests};
+
+/// a `main` for a test target with `harness = false` that runs the tests of the configs as
+/// `cargo test` tests. The paths are relative to the crate
+///
+/// ```no_run
+/// baret_lib::include_suite!("tests/e2e.yaml");
+/// ```
+#[macro_export]
+macro_rules! include_suite {
+    ($($path:expr),+ $(,)?) => {
+        fn main() {
+            let dir = ::std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+            $crate::harness::main(vec![$(dir.join($path)),+])
+        }
+    };
+}
+
+/// reads the configs and runs their tests with the libtest arguments of the command line,
+/// exits with the libtest exit code
+pub fn main(paths: Vec<PathBuf>) -> ! {
+    let suites = match load_suites(paths) {
+        Ok(suites) => suites,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(101)
+        }
+    };
+
+    let mut args = Arguments::from_args();
+    if args.test_threads.is_none() {
+        args.test_threads = suites
+            .iter()
+            .map(|suite| suite.data.global.max_test_concurrency())
+            .min();
+    }
+    let runtime = Arc::new(Runtime::new().expect("unable to start the tokio runtime"));
+
+    let mut trials = Vec::new();
+    let mut set_up = Vec::new();
+    // the tests run in threads of libtest, so all the suites share the containers, every config
+    // still gets its own
+    let containers = Containers::default();
+    // libtest can not stop its tests, so on Ctrl-C or SIGTERM the services and containers are
+    // stopped before exiting
+    let started = Arc::new(Mutex::new(Vec::new()));
+    runtime.spawn(stop_on_signal(started.clone(), containers.clone()));
+    for suite in &suites {
+        let tests = match suite.tests() {
+            Ok(tests) => tests,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(101)
+            }
+        };
+        let services = Services::default();
+        let setup = if args.list || !any_selected(&args, &tests) {
+            Ok(())
+        } else {
+            set_up.push((suite, services.clone()));
+            started
+                .lock()
+                .expect("services lock")
+                .push(services.clone());
+            let ctx = suite
+                .context(&Context::new())
+                .with_containers(containers.clone())
+                .with_services(services.clone());
+            let settings = suite.data.global.stack(&[]);
+            match runtime.block_on(services.start_all(&suite.data.services, &settings, &ctx)) {
+                Err(e) => Err(format!("services failed: {}", e)),
+                Ok(()) => match runtime.block_on(command::pre_setup(&suite.data, &ctx)) {
+                    Some(Err(e)) => Err(format!("before_all failed: {}", e)),
+                    _ => Ok(()),
+                },
+            }
+        };
+        trials.append(&mut suite_trials(
+            suite,
+            tests,
+            &containers,
+            &services,
+            &runtime,
+            setup,
+        ));
+    }
+    trials.sort_by(|a, b| a.name().cmp(b.name()));
+
+    let conclusion = libtest_mimic::run(&args, trials);
+
+    let mut after_all_failed = false;
+    for (suite, services) in set_up {
+        let ctx = suite
+            .context(&Context::new())
+            .with_containers(containers.clone())
+            .with_services(services.clone());
+        if let Some(Err(e)) = runtime.block_on(command::post_setup(&suite.data, &ctx)) {
+            eprintln!("after_all failed: {}", e);
+            after_all_failed = true;
+        }
+        runtime.block_on(services.stop_all());
+    }
+    runtime.block_on(containers.remove_all());
+    if after_all_failed {
+        std::process::exit(101)
+    }
+    conclusion.exit()
+}
+
+async fn stop_on_signal(started: Arc<Mutex<Vec<Services>>>, containers: Containers) {
+    shutdown_signal().await;
+    let started = std::mem::take(&mut *started.lock().expect("services lock"));
+    for services in started {
+        services.stop_all().await;
+    }
+    containers.remove_all().await;
+    std::process::exit(101)
+}
+
+fn load_suites(paths: Vec<PathBuf>) -> Result<Vec<Suite>, Box<dyn std::error::Error>> {
+    let mut suites = Vec::new();
+    for path in paths {
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| format!("unable to read {}: {}", path.display(), e))?;
+        let format = Format::from_path(&path);
+        suites.push(Suite::parse(path, source, format)?);
+    }
+    name_suites(&mut suites)?;
+    Ok(suites)
+}
+
+/// one trial per test, they all fail with the `before_all` error when it failed
+fn suite_trials(
+    suite: &Suite,
+    tests: Tests,
+    containers: &Containers,
+    services: &Services,
+    runtime: &Arc<Runtime>,
+    setup: Result<(), String>,
+) -> Vec<Trial> {
+    let global = Arc::new(suite.data.global.clone());
+
+    tests
+        .into_iter()
+        .map(|(test_name, test)| {
+            let mut ctx = suite
+                .context(&Context::new())
+                .with_containers(containers.clone())
+                .with_services(services.clone())
+                .for_test(&test_name);
+            if let Some(location) = suite.location(suite.local_name(&test_name)) {
+                ctx = ctx.with_location(location);
+            }
+            let runtime = runtime.clone();
+            let global = global.clone();
+            let setup = setup.clone();
+            Trial::test(test_name, move || {
+                setup.map_err(Failed::from)?;
+                let report = runtime.block_on(test.run_arc_settings(global, ctx));
+                report.result.map_err(Failed::from)
+            })
+        })
+        .collect()
+}
+
+/// the same selection as libtest, so the setup only runs for suites with tests that run
+fn any_selected(args: &Arguments, tests: &Tests) -> bool {
+    if args.ignored || args.bench {
+        return false;
+    }
+    let matches = |name: &str, pattern: &str| {
+        if args.exact {
+            name == pattern
+        } else {
+            name.contains(pattern)
+        }
+    };
+
+    tests.keys().any(|name| {
+        args.filter.iter().all(|filter| matches(name, filter))
+            && !args.skip.iter().any(|skip| matches(name, skip))
+    })
+}
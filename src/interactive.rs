@@ -0,0 +1,441 @@
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use regex::bytes::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::time::{timeout_at, Instant};
+
+use crate::command;
+use crate::event::RunEvent;
+use crate::executor::exit_status;
+use crate::output::{self, Captured, CommandOutput, ShowOutput, Stream, Tail};
+use crate::{Context, Error, SettingsStack};
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// one step of an interactive test, it has one of `expect`, `expect_regex`, `send` or
+/// `expect_eof`
+pub struct Step {
+    /// wait until the output contains this text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect: Option<String>,
+    /// wait until the output matches this regex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_regex: Option<String>,
+    /// type this text, use `\n` for enter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send: Option<String>,
+    /// wait until the command closes the terminal, normally because it exited
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub expect_eof: bool,
+    /// miliseconds to wait for this step, default the `timeout` of the test
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+}
+
+enum Action<'a> {
+    Expect(Regex),
+    Send(&'a str),
+    ExpectEof,
+}
+
+impl Step {
+    pub fn expect<S: Into<String>>(text: S) -> Step {
+        Step {
+            expect: Some(text.into()),
+            ..Step::default()
+        }
+    }
+
+    pub fn expect_regex<S: Into<String>>(regex: S) -> Step {
+        Step {
+            expect_regex: Some(regex.into()),
+            ..Step::default()
+        }
+    }
+
+    pub fn send<S: Into<String>>(text: S) -> Step {
+        Step {
+            send: Some(text.into()),
+            ..Step::default()
+        }
+    }
+
+    pub fn expect_eof() -> Step {
+        Step {
+            expect_eof: true,
+            ..Step::default()
+        }
+    }
+
+    /// in miliseconds
+    pub fn with_timeout(mut self, timeout: u32) -> Step {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// checks that the step does exactly one thing and that its regex is valid
+    pub fn check(&self) -> Result<(), String> {
+        self.action().map(|_| ())
+    }
+
+    fn action(&self) -> Result<Action<'_>, String> {
+        let actions = [
+            self.expect.is_some(),
+            self.expect_regex.is_some(),
+            self.send.is_some(),
+            self.expect_eof,
+        ];
+        if actions.iter().filter(|action| **action).count() != 1 {
+            return Err(String::from(
+                "a step needs exactly one of `expect`, `expect_regex`, `send` or `expect_eof`",
+            ));
+        }
+
+        if let Some(text) = &self.expect {
+            return Ok(Action::Expect(
+                Regex::new(&regex::escape(text)).expect("escaped text is a valid regex"),
+            ));
+        }
+        if let Some(regex) = &self.expect_regex {
+            return Regex::new(regex)
+                .map(Action::Expect)
+                .map_err(|e| format!("invalid regex `{}`: {}", regex, e));
+        }
+        if let Some(text) = &self.send {
+            return Ok(Action::Send(text));
+        }
+        Ok(Action::ExpectEof)
+    }
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(text) = &self.expect {
+            write!(f, "expect {:?}", text)
+        } else if let Some(regex) = &self.expect_regex {
+            write!(f, "expect_regex {:?}", regex)
+        } else if let Some(text) = &self.send {
+            write!(f, "send {:?}", text)
+        } else {
+            write!(f, "expect_eof")
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// the step that failed, with everything the terminal showed until then
+pub struct StepFailure {
+    /// starts at 1
+    pub number: usize,
+    pub step: String,
+    pub reason: String,
+    pub transcript: Captured,
+}
+
+impl std::fmt::Display for StepFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "step {} failed: {}", self.number, self.step)?;
+        writeln!(f, "{}", self.reason)?;
+        writeln!(f, "transcript:\n{}", self.transcript)
+    }
+}
+
+enum Stop {
+    Failed(String),
+    TimedOut,
+    Cancelled,
+}
+
+/// the output of the terminal, the part that is not matched yet is in `pending`. Both keep
+/// at most `limit` bytes
+struct Session<'a> {
+    chunks: UnboundedReceiver<Vec<u8>>,
+    pending: Vec<u8>,
+    transcript: Tail,
+    total: u64,
+    eof: bool,
+    ctx: &'a Context,
+    live: bool,
+    line: Vec<u8>,
+    limit: usize,
+    hidden: bool,
+}
+
+impl<'a> Session<'a> {
+    async fn read(&mut self, deadline: Instant) -> Result<(), Stop> {
+        let chunk = tokio::select! {
+            chunk = timeout_at(deadline, self.chunks.recv()) => chunk,
+            _ = self.ctx.cancelled() => return Err(Stop::Cancelled),
+        };
+
+        match chunk {
+            Ok(Some(chunk)) => {
+                if self.live {
+                    output::print_lines(
+                        self.ctx,
+                        Stream::Stdout,
+                        &mut self.line,
+                        &chunk,
+                        self.limit,
+                    );
+                }
+                if self.ctx.events().wants_output() {
+                    self.ctx.emit(RunEvent::OutputChunk {
+                        test_name: self.ctx.test_name().map(String::from),
+                        phase: self.ctx.phase(),
+                        stream: Stream::Stdout,
+                        data: chunk.clone(),
+                    });
+                }
+                self.pending.extend_from_slice(&chunk);
+                let overflow = self.pending.len().saturating_sub(self.limit);
+                self.pending.drain(..overflow);
+                self.transcript.extend(&chunk);
+                self.total += chunk.len() as u64;
+                Ok(())
+            }
+            Ok(None) => {
+                self.eof = true;
+                Ok(())
+            }
+            Err(_) => Err(Stop::TimedOut),
+        }
+    }
+
+    async fn expect(&mut self, regex: &Regex, deadline: Instant) -> Result<(), Stop> {
+        loop {
+            if let Some(found) = regex.find(&self.pending) {
+                let end = found.end();
+                self.pending.drain(..end);
+                return Ok(());
+            }
+            if self.eof {
+                return Err(Stop::Failed(String::from(
+                    "the terminal closed before the output matched",
+                )));
+            }
+            self.read(deadline).await?;
+        }
+    }
+
+    async fn expect_eof(&mut self, deadline: Instant) -> Result<(), Stop> {
+        while !self.eof {
+            self.read(deadline).await?;
+        }
+        Ok(())
+    }
+
+    /// empty with `show_output: never`
+    fn transcript(&self) -> Captured {
+        let mut transcript = Captured {
+            tail: self.transcript.last(self.limit),
+            total: self.total,
+            log: None,
+        };
+        if self.hidden {
+            transcript.clear();
+        }
+        transcript
+    }
+
+    fn finish(&mut self) {
+        if self.live && !self.line.is_empty() {
+            output::print_line(self.ctx, Stream::Stdout, &self.line);
+            self.line.clear();
+        }
+    }
+}
+
+/// runs `script` in a pseudo terminal and goes through the steps. The command has to exit
+/// within the `timeout` after the last step. The terminal is on this machine, so another
+/// executor or an `image` is an error
+pub async fn run(
+    script: &str,
+    steps: &[Step],
+    settings: &SettingsStack<'_, '_>,
+    ctx: &Context,
+) -> Result<CommandOutput, Error> {
+    let argv = command::argv(script, settings);
+    let result = if ctx.executor().is_some()
+        || settings.image().is_some()
+        || !settings.executor().is_local()
+    {
+        Err(Error::IO(io::Error::other(
+            "`steps` run on this machine, they can not be used with `image` or an executor",
+        )))
+    } else {
+        run_steps(&argv, steps, settings, ctx).await
+    };
+    result.map_err(|error| command::with_context(error, argv, settings, ctx))
+}
+
+async fn run_steps(
+    argv: &[String],
+    steps: &[Step],
+    settings: &SettingsStack<'_, '_>,
+    ctx: &Context,
+) -> Result<CommandOutput, Error> {
+    let pair = native_pty_system()
+        .openpty(PtySize::default())
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    let mut command = CommandBuilder::from_argv(argv.iter().map(OsString::from).collect());
+    if settings.clear_env() {
+        command.env_clear();
+    }
+    for (key, value) in settings.env() {
+        command.env(key, value);
+    }
+    let current_dir = std::env::current_dir()?;
+    command.cwd(match ctx.cwd() {
+        Some(cwd) => current_dir.join(cwd),
+        None => current_dir,
+    });
+
+    let mut child = pair
+        .slave
+        .spawn_command(command)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    // the terminal only closes when nobody holds the other end
+    drop(pair.slave);
+    let mut killer = child.clone_killer();
+    let mut reader = pair
+        .master
+        .try_clone_reader()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    let mut writer = pair
+        .master
+        .take_writer()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    let (sender, chunks) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        // reading fails once the command exited
+        while let Ok(length @ 1..) = reader.read(&mut buffer) {
+            if sender.send(buffer[..length].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let limit = settings.output_limit() as usize * 1024;
+    let mut session = Session {
+        chunks,
+        pending: Vec::new(),
+        transcript: Tail::new(limit),
+        total: 0,
+        eof: false,
+        ctx,
+        live: ctx.nocapture() || settings.show_output() == ShowOutput::Always,
+        line: Vec::new(),
+        limit,
+        hidden: settings.show_output() == ShowOutput::Never,
+    };
+
+    for (index, step) in steps.iter().enumerate() {
+        let timeout = step.timeout.unwrap_or_else(|| settings.timeout());
+        let deadline = Instant::now() + Duration::from_millis(timeout.into());
+        let result = match step.action() {
+            Ok(Action::Expect(regex)) => session.expect(&regex, deadline).await,
+            Ok(Action::Send(text)) => writer
+                .write_all(text.as_bytes())
+                .and_then(|_| writer.flush())
+                .map_err(|e| Stop::Failed(format!("unable to send: {}", e))),
+            Ok(Action::ExpectEof) => session.expect_eof(deadline).await,
+            Err(e) => Err(Stop::Failed(e)),
+        };
+
+        if let Err(stop) = result {
+            let _ = killer.kill();
+            session.finish();
+            let reason = match stop {
+                Stop::Cancelled => return Err(Error::Cancelled),
+                Stop::TimedOut => format!("timed out after {} ms", timeout),
+                Stop::Failed(reason) => reason,
+            };
+            return Err(Error::Step(StepFailure {
+                number: index + 1,
+                step: step.to_string(),
+                reason,
+                transcript: session.transcript(),
+            }));
+        }
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(settings.timeout().into());
+    let status = tokio::task::spawn_blocking(move || child.wait());
+    let status = tokio::select! {
+        status = timeout_at(deadline, status) => status,
+        _ = ctx.cancelled() => {
+            let _ = killer.kill();
+            return Err(Error::Cancelled);
+        }
+    };
+    let status = match status {
+        Ok(status) => {
+            let status = status.map_err(io::Error::other)??;
+            exit_status(status.exit_code() as i32)
+        }
+        Err(_) => {
+            let _ = killer.kill();
+            session.finish();
+            return Err(Error::Step(StepFailure {
+                number: steps.len(),
+                step: String::from("after the last step"),
+                reason: format!("the command did not exit within {} ms", settings.timeout()),
+                transcript: session.transcript(),
+            }));
+        }
+    };
+
+    // the rest of the output, the terminal closes right after the command exits
+    let deadline = Instant::now() + Duration::from_millis(100);
+    while !session.eof && session.read(deadline).await.is_ok() {}
+    session.finish();
+
+    let output = CommandOutput {
+        status,
+        stdout: session.transcript(),
+        stderr: Captured::default(),
+    };
+    if command::is_success(settings.should_fail(), output.status.success()) {
+        Ok(output)
+    } else {
+        Err(Error::ExitCode(output))
+    }
+}
+
+#[test]
+fn step_needs_one_action() {
+    assert!(Step::expect("Password:").check().is_ok());
+    assert!(Step::expect_regex("[").check().is_err());
+    assert!(Step::default().check().is_err());
+
+    let step = Step {
+        send: Some(String::from("y\n")),
+        expect_eof: true,
+        ..Step::default()
+    };
+    assert!(step.check().is_err());
+    assert_eq!("send \"y\\n\"", Step::send("y\n").to_string());
+}
+
+#[test]
+fn steps_do_not_run_with_another_executor() {
+    let settings = crate::Settings::default();
+    let ctx = Context::default().with_executor(crate::executor::Mock::default());
+    let steps = [Step::expect_eof()];
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let error = runtime
+        .block_on(run("true", &steps, &settings.stack(&[]), &ctx))
+        .unwrap_err();
+    assert!(error.to_string().contains("`steps` run on this machine"));
+}
@@ -1,42 +1,101 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+pub mod assertion;
 pub mod command;
+pub mod config;
+pub mod context;
+pub mod discover;
 pub mod error;
+pub mod event;
+pub mod executor;
 pub mod expression;
+pub mod harness;
+pub mod interactive;
+pub mod output;
+pub mod runner;
+pub mod service;
 pub mod settings;
+pub mod shard;
+pub mod snapshot;
+pub mod state;
+pub mod suite;
 pub mod tests;
+pub mod timing;
+pub mod validate;
 
-pub use error::Error;
-pub use settings::{GlobalSettings, Settings, SettingsStack};
-pub use tests::{Group, Test, Tests};
+pub use context::Context;
+pub use error::{Error, ErrorContext};
+pub use event::{Events, RunEvent};
+pub use runner::{RunSummary, Runner};
+pub use settings::{GlobalSettings, Settings, SettingsBuilder, SettingsStack};
+pub use tests::{Group, GroupBuilder, Test, TestBuilder, TestReport, Tests};
+pub use timing::Phase;
 
-#[derive(Debug, PartialEq, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Default, Clone, Serialize, Deserialize, JsonSchema)]
 /// Struct for holding the input test data
 pub struct Data {
+    /// scripts that run once, before and after all the tests
     #[serde(default)]
     pub setup: Setup,
+    /// the tests by name, or a group of test files
     pub test: TestsOrGroup,
+    /// settings for all the tests, a test can override them
     #[serde(default)]
     pub global: GlobalSettings,
+    /// commands that run in the background while the tests run, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub services: BTreeMap<String, service::Service>,
 }
 
 impl Data {
+    /// the top level keys in a config file
+    pub const FIELDS: &[&str] = &["setup", "test", "global", "services"];
+
     pub fn dump_example() -> Data {
         let mut example_test = HashMap::new();
         example_test.insert(String::from("just echo"), Test::dump_example());
 
-        let mut base = Data::default();
-        base.setup = Setup::dump_example();
-        base.test = TestsOrGroup::Tests(example_test);
-        base.global = GlobalSettings::default().return_defaults();
+        Data {
+            setup: Setup::dump_example(),
+            test: TestsOrGroup::Tests(example_test),
+            global: GlobalSettings::default().return_defaults(),
+            services: BTreeMap::new(),
+        }
+    }
 
-        base
+    /// JSON schema of the config, for editors to validate and autocomplete it
+    pub fn schema() -> schemars::schema::RootSchema {
+        let mut schema = schemars::schema_for!(Data);
+        strict_schema(&mut schema.schema);
+        for definition in schema.definitions.values_mut() {
+            if let schemars::schema::Schema::Object(object) = definition {
+                strict_schema(object);
+            }
+        }
+        schema
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// unknown keys are an error in the config, so the schema rejects them too
+fn strict_schema(schema: &mut schemars::schema::SchemaObject) {
+    let object = match schema.object.as_mut() {
+        Some(object) if object.additional_properties.is_none() => object,
+        _ => return,
+    };
+    if let Some(slow_threshold) = object.properties.get("slow_threshold").cloned() {
+        object
+            .properties
+            .insert(String::from("warn_after"), slow_threshold);
+    }
+    object.additional_properties = Some(Box::new(schemars::schema::Schema::Bool(false)));
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
+// there is only one per config, boxing the group is not worth it
+#[allow(clippy::large_enum_variant)]
 pub enum TestsOrGroup {
     Tests(Tests),
     Group(Group),
@@ -48,9 +107,13 @@ impl TestsOrGroup {
 
         match self {
             Tests(x) => x.len(),
-            Group(x) => x.files().unwrap().collect::<Vec<_>>().len(),
+            Group(x) => x.files().unwrap().count(),
         }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl Default for TestsOrGroup {
@@ -59,22 +122,49 @@ impl Default for TestsOrGroup {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
 /// setup config
 pub struct Setup {
+    /// script to run before all the tests, when it fails no test runs
     before_all: Option<String>,
+    /// script to run after all the tests, also when tests fail
     after_all: Option<String>,
-
-    #[serde(flatten)]
-    setups: HashMap<String, String>,
 }
 
 impl Setup {
+    /// the keys under `setup`
+    pub const FIELDS: &[&str] = &["before_all", "after_all"];
+
+    pub fn builder() -> SetupBuilder {
+        SetupBuilder::default()
+    }
+
     pub fn dump_example() -> Setup {
         Setup {
             before_all: Some(String::new()),
             after_all: Some(String::new()),
-            setups: HashMap::default(),
         }
     }
 }
+
+#[derive(Debug, Default)]
+/// builds a `Setup` in code
+pub struct SetupBuilder {
+    setup: Setup,
+}
+
+impl SetupBuilder {
+    pub fn before_all<S: Into<String>>(mut self, before_all: S) -> SetupBuilder {
+        self.setup.before_all = Some(before_all.into());
+        self
+    }
+
+    pub fn after_all<S: Into<String>>(mut self, after_all: S) -> SetupBuilder {
+        self.setup.after_all = Some(after_all.into());
+        self
+    }
+
+    pub fn build(self) -> Setup {
+        self.setup
+    }
+}
@@ -1,29 +1,55 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::fs::read_to_string;
+use std::io::{self, Read};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use serde_yaml::from_reader;
 use structopt::StructOpt;
 
-use futures::stream::{self, StreamExt};
 use tokio::runtime::Builder;
 
+use console::Term;
+use futures::StreamExt;
 use indicatif::ProgressBar;
 
-use baret_lib::command;
-use baret_lib::{Data, TestsOrGroup};
+use baret_lib::config::Format;
+use baret_lib::context::{shutdown_signal, LineSink};
+use baret_lib::discover::{discover, suite_name};
+use baret_lib::event::{RunEvent, Status};
+use baret_lib::output::Stream;
+use baret_lib::runner::{Reporter, Selection, SetupFailure, TestOutcome, TestResult};
+use baret_lib::shard::{self, Shard, Strategy};
+use baret_lib::state::{LastRun, STATE_DIR};
+use baret_lib::suite::{name_suites, Suite, STDIN_PATH};
+use baret_lib::validate::validate;
+use baret_lib::{Context, Data, RunSummary, Runner, Tests};
 
-const PROGRESS_BAR_COLOR_TEMPLATE: &'static str =
+const PROGRESS_BAR_COLOR_TEMPLATE: &str =
     "[{elapsed_precise}] {pos:.cyan.bold.bright}/{len:.white.bold.bright} {bar:.cyan/blue}";
-const PROGRESS_BAR_TEMPLATE: &'static str = "[{elapsed_precise}] {pos}/{len} {bar}";
+const PROGRESS_BAR_TEMPLATE: &str = "[{elapsed_precise}] {pos}/{len} {bar}";
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "baret", about = "Bash and Rust End-to-end Testing.")]
 struct Opt {
-    /// Input file
-    #[structopt(short, long, parse(from_os_str), default_value = "baret.yaml")]
-    config: PathBuf,
+    /// Input file, `-` reads it from stdin. Can be given more than once, the test names then
+    /// get the file name as prefix
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        default_value = "baret.yaml",
+        number_of_values = 1
+    )]
+    config: Vec<PathBuf>,
+
+    /// run every `baret.yaml` and `*.baret.yaml` below the current directory, files in
+    /// `.gitignore` are skipped
+    #[structopt(long, conflicts_with = "config")]
+    discover: bool,
+
+    /// directory to discover the configs in, implies `--discover`
+    #[structopt(parse(from_os_str), conflicts_with = "config")]
+    dir: Option<PathBuf>,
 
     /// Verify the input file
     #[structopt(long)]
@@ -33,49 +59,152 @@ struct Opt {
     #[structopt(long)]
     example: bool,
 
+    /// format of the config and the example: yaml, toml or json, default based on the extension
+    #[structopt(long)]
+    format: Option<Format>,
+
+    /// Output the JSON schema of the config, for editors
+    #[structopt(long)]
+    schema: bool,
+
     /// dont show the progress bar
     #[structopt(short, long)]
     quiet: bool,
 
+    /// also report the passed tests with how long they took
+    #[structopt(short, long)]
+    verbose: bool,
+
     /// enable colors in the progress bar
     #[structopt(long)]
     color: bool,
+
+    /// only run the tests that failed in the previous run
+    #[structopt(long, alias = "last-failed", conflicts_with = "failed-first")]
+    failed: bool,
+
+    /// run the tests that failed in the previous run before the other tests
+    #[structopt(long)]
+    failed_first: bool,
+
+    /// stop the run after the first failed test, same as `--max-failures 1`
+    #[structopt(long)]
+    fail_fast: bool,
+
+    /// stop the run after this amount of failed tests, running tests are killed
+    #[structopt(long)]
+    max_failures: Option<NonZeroUsize>,
+
+    /// only run one part of the tests, for example `--shard 2/3` for the second of three parts
+    #[structopt(long)]
+    shard: Option<Shard>,
+
+    /// how tests are divided over the shards: `hash` or `duration` (needs `--shard-timings`)
+    #[structopt(long, default_value = "hash")]
+    shard_strategy: Strategy,
+
+    /// a `last-run.json` with the durations for `--shard-strategy duration`, every shard has
+    /// to get the same file
+    #[structopt(long, parse(from_os_str), required_if("shard-strategy", "duration"))]
+    shard_timings: Option<PathBuf>,
+
+    /// show the given amount of slowest tests at the end of the run
+    #[structopt(long, default_value = "0")]
+    slowest: usize,
+
+    /// print the output of every test while it runs, prefixed with the test name
+    #[structopt(long, alias = "stream")]
+    nocapture: bool,
+
+    /// write the output of the tests with a `snapshot` to their snapshot instead of comparing.
+    /// The snapshot of a test is `__snapshots__/<test>.snap`, with every character but letters,
+    /// digits, `-` and `_` replaced by `_`, and a hash of the name added when something was
+    /// replaced: `logs in` is in `__snapshots__/logs_in-93500bbd.snap`
+    #[structopt(long)]
+    update_snapshots: bool,
+
+    /// list the tests instead of running them
+    #[structopt(long)]
+    list: bool,
+}
+
+impl Opt {
+    fn format(&self, config: &Path) -> Format {
+        self.format.unwrap_or_else(|| Format::from_path(config))
+    }
+
+    fn selection(&self) -> Selection {
+        match (self.failed, self.failed_first) {
+            (true, _) => Selection::Failed,
+            (false, true) => Selection::FailedFirst,
+            (false, false) => Selection::All,
+        }
+    }
+
+    fn max_failures(&self) -> Option<usize> {
+        match (self.max_failures, self.fail_fast) {
+            (Some(max_failures), _) => Some(max_failures.get()),
+            (None, true) => Some(1),
+            (None, false) => None,
+        }
+    }
 }
 
 fn main() {
     let opt = Opt::from_args();
 
     if opt.example {
-        let example =
-            serde_yaml::to_string(&Data::dump_example()).expect("unable to create example");
+        let example = opt
+            .format
+            .unwrap_or(Format::Yaml)
+            .to_string(&Data::dump_example())
+            .expect("unable to create example");
         println!("{}", example);
         return;
     }
 
-    let file = match File::open(&opt.config) {
-        Ok(x) => x,
-        Err(_) => {
-            eprintln!("config {} not found.", opt.config.display());
+    if opt.schema {
+        let schema =
+            serde_json::to_string_pretty(&Data::schema()).expect("unable to create schema");
+        println!("{}", schema);
+        return;
+    }
+
+    let suites = match load_suites(&opt) {
+        Some(x) => x,
+        None => std::process::exit(1),
+    };
+
+    if opt.verify {
+        let mut failed = false;
+        for suite in &suites {
+            if let Err(e) = validate(suite) {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+        if failed {
             std::process::exit(1);
         }
-    };
+        return;
+    }
 
-    let data: Data = match from_reader(BufReader::new(file)) {
-        Ok(x) => x,
-        Err(e) => {
+    if opt.list {
+        if let Err(e) = list_tests(&suites, &opt) {
             eprintln!("{}", e);
             std::process::exit(1);
         }
-    };
-
-    if opt.verify {
         return;
     }
 
-    let pb = create_progression_bar(&opt, data.test.len() as u64);
+    let amount_of_tests = suites
+        .iter()
+        .map(|suite| suite.data.test.len())
+        .sum::<usize>();
+    let pb = create_progression_bar(&opt, amount_of_tests as u64);
     let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
 
-    match runtime.block_on(main_loop(data, pb)) {
+    match runtime.block_on(main_loop(suites, &opt, pb)) {
         Ok(()) => (),
         Err(e) => {
             eprintln!("{}", e);
@@ -84,6 +213,82 @@ fn main() {
     }
 }
 
+/// reads and parses every config, all the errors are printed. `-` is read from stdin
+fn load_suites(opt: &Opt) -> Option<Vec<Suite>> {
+    let root = match (&opt.dir, opt.discover) {
+        (Some(dir), _) => Some(dir.as_path()),
+        (None, true) => Some(Path::new(".")),
+        (None, false) => None,
+    };
+    let configs = match root {
+        Some(root) => match discover(root) {
+            Ok(configs) if configs.is_empty() => {
+                eprintln!("no baret.yaml or *.baret.yaml found in {}", root.display());
+                return None;
+            }
+            Ok(configs) => configs,
+            Err(e) => {
+                eprintln!("unable to discover the configs: {}", e);
+                return None;
+            }
+        },
+        None => opt.config.clone(),
+    };
+
+    let mut suites = Vec::new();
+    let mut failed = false;
+    for config in &configs {
+        let (path, source) = if config == Path::new("-") {
+            let mut source = String::new();
+            match std::io::stdin().read_to_string(&mut source) {
+                Ok(_) => (PathBuf::from(STDIN_PATH), source),
+                Err(e) => {
+                    eprintln!("unable to read the config from stdin: {}", e);
+                    failed = true;
+                    continue;
+                }
+            }
+        } else {
+            match read_to_string(config) {
+                Ok(source) => (config.clone(), source),
+                Err(_) => {
+                    eprintln!("config {} not found.", config.display());
+                    failed = true;
+                    continue;
+                }
+            }
+        };
+
+        match Suite::parse(path, source, opt.format(config)) {
+            Ok(mut suite) => {
+                if let Some(root) = root {
+                    suite.dir = config.parent().map(PathBuf::from);
+                    suite.name = Some(suite_name(root, config));
+                }
+                suites.push(suite)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+
+    if suites.len() == 1 {
+        suites[0].name = None;
+    }
+    if let Err(e) = name_suites(&mut suites) {
+        eprintln!("{}", e);
+        failed = true;
+    }
+
+    if failed {
+        None
+    } else {
+        Some(suites)
+    }
+}
+
 fn create_progression_bar(opt: &Opt, amount_of_tasks: u64) -> ProgressBar {
     let pb = if opt.quiet {
         indicatif::ProgressBar::hidden()
@@ -112,57 +317,66 @@ fn create_progression_bar(opt: &Opt, amount_of_tasks: u64) -> ProgressBar {
     pb
 }
 
-async fn main_loop(data: Data, pb: ProgressBar) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(result) = command::pre_setup(&data).await {
-        result?
+async fn main_loop(
+    suites: Vec<Suite>,
+    opt: &Opt,
+    pb: ProgressBar,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = Context::new()
+        .with_sink(line_sink(&pb))
+        .with_nocapture(opt.nocapture)
+        .with_update_snapshots(opt.update_snapshots)
+        .with_log_dir(Path::new(STATE_DIR).join("logs"));
+    // the run is cancelled instead of ended, so the `after_all` scripts still run and the
+    // services and containers are stopped
+    let cancel = ctx.clone();
+    let stopped = ctx.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        eprintln!("stopping the run, press Ctrl-C again to exit without cleaning up");
+        cancel.cancel();
+        shutdown_signal().await;
+        std::process::exit(130);
+    });
+    let mut runner = Runner::from_suites(suites)
+        .with_context(ctx)
+        .with_last_run(load_last_run())
+        .with_selection(opt.selection())
+        .with_reporter(ConsoleReporter {
+            verbose: opt.verbose,
+        });
+    if let Some(shard) = opt.shard {
+        runner = runner.with_shard(shard, opt.shard_strategy);
+    }
+    if let Some(timings) = load_shard_timings(opt)? {
+        runner = runner.with_shard_timings(timings);
+    }
+    if let Some(max_failures) = opt.max_failures() {
+        runner = runner.with_max_failures(max_failures);
     }
 
-    let global_settings = Arc::new(data.global.clone());
-
-    let tests = match data.test.clone() {
-        TestsOrGroup::Tests(tests) => tests,
-        TestsOrGroup::Group(group) => group.into_tests()?,
-    };
+    let progress = tokio::spawn(show_progress(runner.events_without_output(), pb));
+    let summary = runner.run().await?;
+    progress.await?;
 
-    let mut tasks = stream::iter(tests)
-        .map(|(test_name, test)| {
-            let global_settings = global_settings.clone();
-            tokio::spawn(async move {
-                match test.run_arc_settings(global_settings).await {
-                    Ok(()) => false,
-                    Err(err) => {
-                        eprintln!("Failed test: '{}'", test_name);
-                        eprintln!("{}", err);
-                        true
-                    }
-                }
-            })
-        })
-        .buffer_unordered(global_settings.max_test_concurrency());
-
-    let mut successes = 0usize;
-    let mut errors = 0usize;
-    while let Some(task) = tasks.next().await {
-        if task? {
-            errors += 1;
-        } else {
-            successes += 1;
-            pb.inc(1);
+    print_slowest(&summary, opt.slowest);
+    if let Some(last_run) = &summary.last_run {
+        if let Err(e) = last_run.save() {
+            eprintln!(
+                "warning: unable to write {}: {}",
+                LastRun::path().display(),
+                e
+            );
         }
     }
-    pb.finish();
-
-    if let Some(result) = command::post_setup(&data).await {
-        result?
-    }
 
-    if errors != 0 {
+    if !summary.is_success() {
+        Err(summary.to_string().into())
+    } else if stopped.is_cancelled() {
         Err(format!(
-            "Error: {} {} had errors out of {} {}",
-            errors,
-            test_or_tests(errors),
-            successes + errors,
-            test_or_tests(successes + errors)
+            "Stopped before all tests ran: {} passed, {} not run",
+            summary.passed(),
+            summary.not_run()
         )
         .into())
     } else {
@@ -170,10 +384,192 @@ async fn main_loop(data: Data, pb: ProgressBar) -> Result<(), Box<dyn std::error
     }
 }
 
-fn test_or_tests(amount: usize) -> &'static str {
-    if amount == 1 {
-        "test"
-    } else {
-        "tests"
+/// moves the progress bar until the run is done
+async fn show_progress<S: futures::Stream<Item = RunEvent> + Unpin>(
+    mut events: S,
+    pb: ProgressBar,
+) {
+    let mut queued = 0;
+    while let Some(event) = events.next().await {
+        match event {
+            RunEvent::TestQueued { .. } => {
+                queued += 1;
+                pb.set_length(queued);
+            }
+            RunEvent::TestFinished {
+                status: Status::Passed,
+                ..
+            } => pb.inc(1),
+            _ => (),
+        }
+    }
+    pb.finish();
+}
+
+/// prints the failures, and the passed tests when `verbose`
+struct ConsoleReporter {
+    verbose: bool,
+}
+
+impl Reporter for ConsoleReporter {
+    fn test_finished(&mut self, result: &TestResult) {
+        if result.is_slow() {
+            eprintln!(
+                "Slow test: '{}' took {:.2?}, more than the slow_threshold of {:?}",
+                result.name,
+                result.timings.total(),
+                result.slow_threshold.unwrap_or_default()
+            );
+        }
+
+        match &result.outcome {
+            TestOutcome::Failed(err) => {
+                eprintln!("Failed test: '{}'", result.name);
+                eprintln!("{}", err);
+                eprintln!("took {}", result.timings);
+            }
+            TestOutcome::Passed if self.verbose => {
+                eprintln!("Passed test: '{}' took {}", result.name, result.timings);
+            }
+            TestOutcome::Passed | TestOutcome::Cancelled | TestOutcome::NotRun => (),
+        }
+    }
+
+    fn setup_failed(&mut self, failure: &SetupFailure) {
+        eprintln!("Failed setup");
+        eprintln!("{}", failure.error);
+    }
+
+    fn warning(&mut self, message: &str) {
+        eprintln!("warning: {}", message);
+    }
+}
+
+/// print above the progress bar, or straight to the terminal when there is no bar. The lines
+/// stay on the stream they were written to
+fn line_sink(pb: &ProgressBar) -> LineSink {
+    let pb = pb.clone();
+    let lock = Mutex::new(());
+    Arc::new(move |stream, line| {
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        match (pb.is_hidden(), stream) {
+            (false, Stream::Stdout) => {
+                // the bar is drawn on stderr with the cursor below it, print the line in its
+                // place and leave an empty line for the bar to redraw in
+                let _ = Term::stderr().clear_last_lines(1);
+                println!("{}", line);
+                eprintln!();
+                pb.println("");
+            }
+            (false, Stream::Stderr) => pb.println(line),
+            (true, Stream::Stdout) => println!("{}", line),
+            (true, Stream::Stderr) => eprintln!("{}", line),
+        }
+    })
+}
+
+fn load_last_run() -> LastRun {
+    LastRun::load().unwrap_or_else(|e| {
+        eprintln!(
+            "warning: unable to read {}, ignoring it: {}",
+            LastRun::path().display(),
+            e
+        );
+        LastRun::default()
+    })
+}
+
+/// the `--shard-timings` file, a missing file has no durations
+fn load_shard_timings(opt: &Opt) -> Result<Option<LastRun>, baret_lib::Error> {
+    match &opt.shard_timings {
+        Some(path) => Ok(Some(LastRun::load_from(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("unable to read {}: {}", path.display(), e),
+            )
+        })?)),
+        None => Ok(None),
+    }
+}
+
+fn list_tests(suites: &[Suite], opt: &Opt) -> Result<(), baret_lib::Error> {
+    let mut tests = Tests::new();
+    for suite in suites {
+        tests.extend(suite.tests()?);
+    }
+    let durations = load_shard_timings(opt)?
+        .map(|timings| timings.durations(suites))
+        .unwrap_or_default();
+    let shards = opt.shard.map(|shard| {
+        if opt.shard_strategy == Strategy::Duration {
+            if let Some(warning) = shard::missing_durations(&tests, &durations) {
+                eprintln!("warning: {}", warning);
+            }
+        }
+        let shards = shard::assign_tests(&tests, shard.total, opt.shard_strategy, &durations);
+        (shards, shard.total)
+    });
+
+    let shard = |test_name: &str| match &shards {
+        Some((shards, total)) => format!(" [shard {}/{}]", shards[test_name], total),
+        None => String::new(),
+    };
+
+    if suites.len() < 2 {
+        let mut test_names: Vec<_> = tests.keys().collect();
+        test_names.sort();
+        for test_name in test_names {
+            println!("{}{}", test_name, shard(test_name));
+        }
+        return Ok(());
+    }
+
+    // the suites as a tree of their names, with their tests below them
+    let mut suites: Vec<&Suite> = suites.iter().collect();
+    suites.sort_by_key(|suite| suite.name.clone());
+    let mut previous_dirs: Vec<&str> = Vec::new();
+    for suite in suites {
+        let mut dirs: Vec<&str> = suite
+            .name
+            .as_deref()
+            .unwrap_or_default()
+            .split('/')
+            .collect();
+        let file = dirs.pop().unwrap_or_default();
+        let shared = dirs
+            .iter()
+            .zip(&previous_dirs)
+            .take_while(|(dir, previous)| dir == previous)
+            .count();
+        for (depth, dir) in dirs.iter().enumerate().skip(shared) {
+            println!("{}{}/", "  ".repeat(depth), dir);
+        }
+        println!("{}{}", "  ".repeat(dirs.len()), file);
+
+        let mut test_names: Vec<String> = suite.tests()?.into_keys().collect();
+        test_names.sort();
+        for test_name in test_names {
+            println!(
+                "{}{}{}",
+                "  ".repeat(dirs.len() + 1),
+                suite.local_name(&test_name),
+                shard(&test_name)
+            );
+        }
+        previous_dirs = dirs;
+    }
+
+    Ok(())
+}
+
+fn print_slowest(summary: &RunSummary, amount: usize) {
+    let slowest = summary.slowest(amount);
+    if slowest.is_empty() {
+        return;
+    }
+
+    eprintln!("Slowest tests:");
+    for result in slowest {
+        eprintln!("  {}: {}", result.name, result.timings);
     }
 }
@@ -0,0 +1,235 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use tokio::fs::File;
+use tokio::io::{self, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, BufWriter};
+
+use crate::event::RunEvent;
+use crate::Context;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// the output streams of a command
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+/// when the output of a test is shown
+pub enum ShowOutput {
+    /// print every line while the test runs
+    Always,
+    /// only show the output in the failure message
+    #[default]
+    OnFailure,
+    /// do not show the output at all
+    Never,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+/// the end of the output of a command, the full output is written to `log`
+pub struct Captured {
+    /// the last bytes of the output
+    pub tail: Vec<u8>,
+    /// amount of bytes the command wrote
+    pub total: u64,
+    pub log: Option<PathBuf>,
+}
+
+impl Captured {
+    pub fn is_truncated(&self) -> bool {
+        self.total > self.tail.len() as u64
+    }
+
+    /// forget the output, for tests that should not show it
+    pub fn clear(&mut self) {
+        self.tail.clear();
+        self.total = 0;
+    }
+}
+
+#[derive(Debug)]
+/// exit status and output of a finished command
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: Captured,
+    pub stderr: Captured,
+}
+
+/// ring buffer that keeps the last `limit` bytes
+pub(crate) struct Tail {
+    buffer: VecDeque<u8>,
+    limit: usize,
+}
+
+impl Tail {
+    pub(crate) fn new(limit: usize) -> Tail {
+        Tail {
+            buffer: VecDeque::new(),
+            limit,
+        }
+    }
+
+    pub(crate) fn extend(&mut self, bytes: &[u8]) {
+        let bytes = &bytes[bytes.len().saturating_sub(self.limit)..];
+        let overflow = (self.buffer.len() + bytes.len()).saturating_sub(self.limit);
+        self.buffer.drain(..overflow);
+        self.buffer.extend(bytes);
+    }
+
+    /// the last `amount` bytes
+    pub(crate) fn last(&self, amount: usize) -> Vec<u8> {
+        let start = self.buffer.len().saturating_sub(amount);
+        self.buffer.range(start..).copied().collect()
+    }
+}
+
+/// reads `reader` until it closes, keeping only the last `limit` bytes in memory, the full
+/// output is written to `log`. When `live` is set every line is also passed to the context
+pub async fn capture<R: AsyncRead + Unpin>(
+    reader: R,
+    stream: Stream,
+    ctx: &Context,
+    live: bool,
+    limit: usize,
+    log: Option<PathBuf>,
+) -> io::Result<Captured> {
+    let mut log_file = match &log {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            Some(BufWriter::new(File::create(path).await?))
+        }
+        None => None,
+    };
+
+    let mut reader = BufReader::new(reader);
+    let mut tail = Tail::new(limit);
+    let mut total = 0u64;
+    let mut line = Vec::new();
+    let events = ctx.events().wants_output();
+
+    loop {
+        let chunk = reader.fill_buf().await?;
+        if chunk.is_empty() {
+            break;
+        }
+        let length = chunk.len();
+        total += length as u64;
+        tail.extend(chunk);
+        if let Some(log_file) = log_file.as_mut() {
+            log_file.write_all(chunk).await?;
+        }
+        if events {
+            ctx.emit(RunEvent::OutputChunk {
+                test_name: ctx.test_name().map(String::from),
+                phase: ctx.phase(),
+                stream,
+                data: chunk.to_vec(),
+            });
+        }
+        if live {
+            print_lines(ctx, stream, &mut line, chunk, limit);
+        }
+        reader.consume(length);
+    }
+
+    if !line.is_empty() {
+        print_line(ctx, stream, &line);
+    }
+    if let Some(log_file) = log_file.as_mut() {
+        log_file.flush().await?;
+    }
+
+    Ok(Captured {
+        tail: tail.buffer.into(),
+        total,
+        log,
+    })
+}
+
+/// passes the finished lines in `chunk` to the context, the unfinished end stays in `line`.
+/// Lines longer than `limit` are split
+pub fn print_lines(ctx: &Context, stream: Stream, line: &mut Vec<u8>, chunk: &[u8], limit: usize) {
+    for byte in chunk {
+        if *byte == b'\n' || line.len() >= limit {
+            print_line(ctx, stream, line);
+            line.clear();
+        }
+        if *byte != b'\n' {
+            line.push(*byte);
+        }
+    }
+}
+
+pub fn print_line(ctx: &Context, stream: Stream, line: &[u8]) {
+    let text = String::from_utf8_lossy(line);
+    ctx.print_line(stream, text.trim_end_matches('\r'));
+}
+
+impl std::fmt::Display for Captured {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_truncated() {
+            let left_out = self.total - self.tail.len() as u64;
+            match &self.log {
+                Some(log) => writeln!(
+                    f,
+                    "[... {} bytes left out, full output in {}]",
+                    left_out,
+                    log.display()
+                )?,
+                None => writeln!(f, "[... {} bytes left out]", left_out)?,
+            }
+        }
+        match std::str::from_utf8(&self.tail) {
+            Ok(text) => write!(f, "{}", text),
+            Err(_) if self.is_truncated() => write!(f, "{}", String::from_utf8_lossy(&self.tail)),
+            Err(_) => write!(f, "not utf8 string"),
+        }
+    }
+}
+
+#[test]
+fn tail_keeps_the_last_bytes() {
+    let mut tail = Tail::new(4);
+    tail.extend(b"ab");
+    tail.extend(b"cde");
+    assert_eq!(b"bcde".to_vec(), Vec::from(tail.buffer.clone()));
+
+    tail.extend(b"0123456789");
+    assert_eq!(b"6789".to_vec(), Vec::from(tail.buffer));
+}
+
+#[test]
+fn captured_shows_truncation() {
+    let captured = Captured {
+        tail: b"end\n".to_vec(),
+        total: 10,
+        log: None,
+    };
+
+    assert_eq!("[... 6 bytes left out]\nend\n", captured.to_string());
+
+    let captured = Captured {
+        log: Some(PathBuf::from("logs/test.stdout")),
+        ..captured
+    };
+
+    assert_eq!(
+        "[... 6 bytes left out, full output in logs/test.stdout]\nend\n",
+        captured.to_string()
+    );
+}
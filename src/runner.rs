@@ -0,0 +1,777 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future;
+use futures::stream::{self, StreamExt};
+use tokio::io;
+
+use crate::command;
+use crate::event::{EventStream, Events, RunEvent, Status};
+use crate::executor::Containers;
+use crate::output::CommandOutput;
+use crate::service::Services;
+use crate::shard::{self, Shard, Strategy};
+use crate::state::{LastRun, Outcome};
+use crate::suite::Suite;
+use crate::timing::{Phase, Timings};
+use crate::{Context, Data, Error, Test, Tests};
+
+/// decides if a test runs, gets the name of the test in the run
+pub type Filter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+/// which tests run, based on the outcomes of the previous run
+pub enum Selection {
+    #[default]
+    All,
+    /// all the tests, the ones that failed in the previous run first
+    FailedFirst,
+    /// only the tests that failed in the previous run
+    Failed,
+}
+
+/// gets told what happens during a run, all the methods do nothing by default
+pub trait Reporter: Send {
+    /// the amount of tests that will run is known
+    fn run_started(&mut self, _total: usize) {}
+
+    /// called for every test that ran, also for tests that got cancelled
+    fn test_finished(&mut self, _result: &TestResult) {}
+
+    fn setup_failed(&mut self, _failure: &SetupFailure) {}
+
+    /// something is off, but the run goes on
+    fn warning(&mut self, _message: &str) {}
+
+    fn run_finished(&mut self, _summary: &RunSummary) {}
+}
+
+#[derive(Debug)]
+pub enum TestOutcome {
+    Passed,
+    Failed(Error),
+    /// killed while it ran, because the run was stopped
+    Cancelled,
+    /// never started, because the run was stopped or `before_all` failed
+    NotRun,
+}
+
+#[derive(Debug)]
+/// what happened to a single test
+pub struct TestResult {
+    /// the name in the run, with the suite as prefix when there is more than one suite
+    pub name: String,
+    pub suite: Option<String>,
+    pub outcome: TestOutcome,
+    pub timings: Timings,
+    /// output of the `test` script when it passed and `Runner::with_passed_output` is set, for
+    /// a failed test it is in the error
+    pub output: Option<CommandOutput>,
+    pub slow_threshold: Option<Duration>,
+}
+
+impl TestResult {
+    fn not_run(name: String, suite: &Suite) -> TestResult {
+        TestResult {
+            name,
+            suite: suite.name.clone(),
+            outcome: TestOutcome::NotRun,
+            timings: Timings::default(),
+            output: None,
+            slow_threshold: None,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match self.outcome {
+            TestOutcome::Passed => Status::Passed,
+            TestOutcome::Failed(_) => Status::Failed,
+            TestOutcome::Cancelled => Status::Cancelled,
+            TestOutcome::NotRun => Status::NotRun,
+        }
+    }
+
+    pub fn is_slow(&self) -> bool {
+        self.slow_threshold
+            .is_some_and(|limit| self.timings.total() > limit)
+    }
+
+    /// the output of the `test` script, or of the phase that failed
+    pub fn output(&self) -> Option<&CommandOutput> {
+        match &self.outcome {
+            TestOutcome::Failed(error) => error.output(),
+            _ => self.output.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// a `before_all` or `after_all` that failed
+pub struct SetupFailure {
+    pub suite: Option<String>,
+    pub phase: Phase,
+    pub error: Error,
+    /// tests that did not run because of it, only `before_all` stops tests
+    pub tests_not_run: usize,
+}
+
+impl SetupFailure {
+    /// `before_all`, or `before_all of 'suite'` when the run has more than one suite
+    pub fn name(&self) -> String {
+        match &self.suite {
+            Some(suite) => format!("{} of '{}'", self.phase, suite),
+            None => self.phase.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// the outcome of a run
+pub struct RunSummary {
+    /// the tests that ran in the order they finished, followed by the tests that did not run
+    pub tests: Vec<TestResult>,
+    pub setup_failures: Vec<SetupFailure>,
+    /// the previous run given with `with_last_run`, updated with this run
+    pub last_run: Option<LastRun>,
+}
+
+impl RunSummary {
+    pub fn passed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TestOutcome::Passed))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TestOutcome::Failed(_)))
+    }
+
+    /// tests that were cancelled or never started
+    pub fn not_run(&self) -> usize {
+        self.count(|outcome| matches!(outcome, TestOutcome::Cancelled | TestOutcome::NotRun))
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0 && self.setup_failures.is_empty()
+    }
+
+    /// the slowest tests that ran, slowest first
+    pub fn slowest(&self, amount: usize) -> Vec<&TestResult> {
+        let mut tests: Vec<_> = self
+            .tests
+            .iter()
+            .filter(|test| !matches!(test.outcome, TestOutcome::NotRun))
+            .collect();
+        tests.sort_by(|a, b| {
+            b.timings
+                .total()
+                .cmp(&a.timings.total())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        tests.truncate(amount);
+        tests
+    }
+
+    fn count<F: Fn(&TestOutcome) -> bool>(&self, f: F) -> usize {
+        self.tests.iter().filter(|test| f(&test.outcome)).count()
+    }
+}
+
+/// runs the tests of one or more suites: the setup of a suite runs around its own tests, the
+/// tests of a suite run at the same time up to its `max_test_concurrency`
+pub struct Runner {
+    suites: Vec<Suite>,
+    ctx: Context,
+    filter: Option<Filter>,
+    selection: Selection,
+    last_run: Option<LastRun>,
+    shard: Option<(Shard, Strategy)>,
+    shard_timings: Option<LastRun>,
+    max_concurrency: Option<usize>,
+    max_failures: Option<usize>,
+    keep_output: bool,
+    reporters: Vec<Box<dyn Reporter>>,
+    events: Events,
+}
+
+impl Runner {
+    pub fn new(data: Data) -> Runner {
+        Runner::from_suites(vec![Suite::new(data)])
+    }
+
+    pub fn from_suites(suites: Vec<Suite>) -> Runner {
+        Runner {
+            suites,
+            ctx: Context::new(),
+            filter: None,
+            selection: Selection::default(),
+            last_run: None,
+            shard: None,
+            shard_timings: None,
+            max_concurrency: None,
+            max_failures: None,
+            keep_output: false,
+            reporters: Vec::new(),
+            events: Events::default(),
+        }
+    }
+
+    /// the context the tests run with, for streaming output, log files and cancelling the run
+    pub fn with_context(mut self, ctx: Context) -> Runner {
+        self.ctx = ctx;
+        self
+    }
+
+    /// only run the tests for which `filter` returns true
+    pub fn with_filter<F: Fn(&str) -> bool + Send + Sync + 'static>(mut self, filter: F) -> Runner {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// the outcomes of the previous run, used by the selection. The updated version is in the
+    /// summary
+    pub fn with_last_run(mut self, last_run: LastRun) -> Runner {
+        self.last_run = Some(last_run);
+        self
+    }
+
+    pub fn with_selection(mut self, selection: Selection) -> Runner {
+        self.selection = selection;
+        self
+    }
+
+    /// only run the tests of one shard
+    pub fn with_shard(mut self, shard: Shard, strategy: Strategy) -> Runner {
+        self.shard = Some((shard, strategy));
+        self
+    }
+
+    /// the durations for `Strategy::Duration`, every shard needs the same ones
+    pub fn with_shard_timings(mut self, timings: LastRun) -> Runner {
+        self.shard_timings = Some(timings);
+        self
+    }
+
+    /// overrides the `max_test_concurrency` of the suites
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Runner {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// stop the run after this amount of failed tests, running tests are killed
+    pub fn with_max_failures(mut self, max_failures: usize) -> Runner {
+        self.max_failures = Some(max_failures);
+        self
+    }
+
+    /// keep the output of the passed tests in the `RunSummary`, by default only the failed
+    /// tests have their output so the memory does not grow with every test
+    pub fn with_passed_output(mut self, keep_output: bool) -> Runner {
+        self.keep_output = keep_output;
+        self
+    }
+
+    pub fn with_reporter<R: Reporter + 'static>(mut self, reporter: R) -> Runner {
+        self.reporters.push(Box::new(reporter));
+        self
+    }
+
+    /// the events of the run, subscribe before calling `run`. The stream ends when the run is
+    /// done
+    pub fn events(&self) -> EventStream {
+        self.events.subscribe()
+    }
+
+    /// like `events` but without the `OutputChunk`s
+    pub fn events_without_output(&self) -> EventStream {
+        self.events.subscribe_without_output()
+    }
+
+    /// runs the tests, only fails when the tests can not be read or a task panics
+    pub async fn run(mut self) -> Result<RunSummary, Error> {
+        let mut tests = Tests::new();
+        let mut owners = HashMap::new();
+        for (index, suite) in self.suites.iter().enumerate() {
+            for (test_name, test) in suite.tests()? {
+                owners.insert(test_name.clone(), index);
+                tests.insert(test_name, test);
+            }
+        }
+
+        let known_tests: HashSet<String> = tests.keys().cloned().collect();
+        if let Some(filter) = &self.filter {
+            tests.retain(|test_name, _| filter(test_name));
+        }
+        if let Some((shard, strategy)) = self.shard {
+            let durations = self
+                .shard_timings
+                .as_ref()
+                .map(|timings| timings.durations(&self.suites))
+                .unwrap_or_default();
+            if strategy == Strategy::Duration {
+                if let Some(warning) = shard::missing_durations(&tests, &durations) {
+                    self.report(|reporter| reporter.warning(&warning));
+                }
+            }
+            let shards = shard::assign_tests(&tests, shard.total, strategy, &durations);
+            tests.retain(|test_name, _| shards[test_name] == shard.index);
+        }
+        let tests = self.select(tests);
+        self.report(|reporter| reporter.run_started(tests.len()));
+        self.ctx = self.ctx.clone().with_events(self.events.clone());
+        for (test_name, _) in &tests {
+            self.events.emit(RunEvent::TestQueued {
+                test_name: test_name.clone(),
+            });
+        }
+
+        let mut suite_tests = vec![Vec::new(); self.suites.len()];
+        for (test_name, test) in tests {
+            suite_tests[owners[&test_name]].push((test_name, test));
+        }
+
+        let mut summary = RunSummary::default();
+        let mut after_all_failures = Vec::new();
+        let suites = std::mem::take(&mut self.suites);
+        for (suite, tests) in suites.iter().zip(suite_tests) {
+            if tests.is_empty() {
+                continue;
+            }
+            let start = summary.tests.len();
+            if let Some(failure) = self.run_with_setup(suite, tests, &mut summary).await? {
+                after_all_failures.push(failure);
+            }
+
+            let results = &summary.tests[start..];
+            let count = |status| {
+                results
+                    .iter()
+                    .filter(|test| test.status() == status)
+                    .count()
+            };
+            self.events.emit(RunEvent::SuiteFinished {
+                suite: suite.name.clone(),
+                passed: count(Status::Passed),
+                failed: count(Status::Failed),
+                not_run: results.len() - count(Status::Passed) - count(Status::Failed),
+            });
+        }
+        summary.setup_failures.append(&mut after_all_failures);
+
+        if let Some(mut last_run) = self.last_run.take() {
+            for test in &summary.tests {
+                let outcome = match test.outcome {
+                    TestOutcome::Passed => Outcome::Passed,
+                    TestOutcome::Failed(_) => Outcome::Failed,
+                    TestOutcome::Cancelled | TestOutcome::NotRun => continue,
+                };
+                let suite = &suites[owners[&test.name]];
+                last_run.record(
+                    &suite.config_key(),
+                    suite.local_name(&test.name),
+                    outcome,
+                    test.timings.total(),
+                );
+            }
+            for suite in &suites {
+                last_run.retain(&suite.config_key(), |test_name| {
+                    known_tests.contains(&suite.test_name(test_name))
+                });
+            }
+            summary.last_run = Some(last_run);
+        }
+
+        self.report(|reporter| reporter.run_finished(&summary));
+        self.events.close();
+        Ok(summary)
+    }
+
+    /// runs the tests of a suite between its `before_all` and `after_all`, returns the
+    /// `after_all` failure so it can be reported after all the tests. The `services` start
+    /// before `before_all`, they are stopped and the containers started for `reuse_container`
+    /// are removed at the end
+    async fn run_with_setup(
+        &mut self,
+        suite: &Suite,
+        tests: Vec<(String, Test)>,
+        summary: &mut RunSummary,
+    ) -> Result<Option<SetupFailure>, Error> {
+        if self.ctx.is_cancelled() {
+            self.not_run(
+                suite,
+                tests.into_iter().map(|(test_name, _)| test_name),
+                summary,
+            );
+            return Ok(None);
+        }
+
+        let containers = Containers::default();
+        let services = Services::default();
+        let setup_ctx = suite
+            .context(&Context::new().with_events(self.events.clone()))
+            .with_containers(containers.clone())
+            .with_services(services.clone());
+        let failure = self
+            .run_between_setup(suite, tests, summary, &setup_ctx)
+            .await;
+        services.stop_all().await;
+        containers.remove_all().await;
+        failure
+    }
+
+    async fn run_between_setup(
+        &mut self,
+        suite: &Suite,
+        tests: Vec<(String, Test)>,
+        summary: &mut RunSummary,
+        setup_ctx: &Context,
+    ) -> Result<Option<SetupFailure>, Error> {
+        let settings = suite.data.global.stack(&[]);
+        let services = setup_ctx
+            .services()
+            .start_all(&suite.data.services, &settings, setup_ctx)
+            .await;
+        let pre_setup = match services {
+            Ok(()) => command::pre_setup(&suite.data, setup_ctx).await,
+            Err(error) => Some(Err(error.in_phase(Phase::BeforeAll))),
+        };
+        if let Some(Err(error)) = pre_setup {
+            let failure = SetupFailure {
+                suite: suite.name.clone(),
+                phase: Phase::BeforeAll,
+                error,
+                tests_not_run: tests.len(),
+            };
+            self.report(|reporter| reporter.setup_failed(&failure));
+            summary.setup_failures.push(failure);
+            self.not_run(
+                suite,
+                tests.into_iter().map(|(test_name, _)| test_name),
+                summary,
+            );
+            return Ok(None);
+        }
+
+        self.run_suite(suite, tests, summary, setup_ctx).await?;
+
+        if let Some(Err(error)) = command::post_setup(&suite.data, setup_ctx).await {
+            let failure = SetupFailure {
+                suite: suite.name.clone(),
+                phase: Phase::AfterAll,
+                error,
+                tests_not_run: 0,
+            };
+            self.report(|reporter| reporter.setup_failed(&failure));
+            return Ok(Some(failure));
+        }
+
+        Ok(None)
+    }
+
+    async fn run_suite(
+        &mut self,
+        suite: &Suite,
+        tests: Vec<(String, Test)>,
+        summary: &mut RunSummary,
+        setup_ctx: &Context,
+    ) -> Result<(), Error> {
+        let global_settings = Arc::new(suite.data.global.clone());
+        let max_concurrency = self
+            .max_concurrency
+            .unwrap_or_else(|| global_settings.max_test_concurrency());
+        let ctx = self.ctx.clone();
+        let max_failures = self.max_failures;
+        let suite_ctx = suite
+            .context(&ctx)
+            .with_containers(setup_ctx.containers().clone())
+            .with_services(setup_ctx.services().clone());
+        let mut not_started: HashSet<String> = tests
+            .iter()
+            .map(|(test_name, _)| test_name.clone())
+            .collect();
+
+        let mut tasks = stream::iter(tests)
+            .take_while(|_| future::ready(!ctx.is_cancelled()))
+            .map(|(test_name, test)| {
+                let global_settings = global_settings.clone();
+                let mut ctx = suite_ctx.for_test(&test_name);
+                if let Some(location) = suite.location(suite.local_name(&test_name)) {
+                    ctx = ctx.with_location(location);
+                }
+                tokio::spawn(async move {
+                    let slow_threshold = global_settings
+                        .stack(&[&test.settings])
+                        .slow_threshold()
+                        .map(|ms| Duration::from_millis(ms.into()));
+                    let report = test.run_arc_settings(global_settings, ctx).await;
+                    (test_name, report, slow_threshold)
+                })
+            })
+            .buffer_unordered(max_concurrency);
+
+        let mut failures = summary.failed();
+        while let Some(task) = tasks.next().await {
+            let (test_name, report, slow_threshold) =
+                task.map_err(|e| Error::IO(io::Error::other(e)))?;
+            not_started.remove(&test_name);
+
+            let outcome = match report.result {
+                Ok(()) => TestOutcome::Passed,
+                Err(Error::Cancelled) => TestOutcome::Cancelled,
+                Err(error) => {
+                    failures += 1;
+                    if max_failures.is_some_and(|max| failures >= max) {
+                        ctx.cancel();
+                    }
+                    TestOutcome::Failed(error)
+                }
+            };
+            let result = TestResult {
+                name: test_name,
+                suite: suite.name.clone(),
+                outcome,
+                timings: report.timings,
+                output: report.output.filter(|_| self.keep_output),
+                slow_threshold,
+            };
+            for reporter in self.reporters.iter_mut() {
+                reporter.test_finished(&result);
+            }
+            self.finished(&result);
+            summary.tests.push(result);
+        }
+        drop(tasks);
+
+        let mut not_started: Vec<String> = not_started.into_iter().collect();
+        not_started.sort();
+        self.not_run(suite, not_started, summary);
+
+        Ok(())
+    }
+
+    fn not_run<I: IntoIterator<Item = String>>(
+        &self,
+        suite: &Suite,
+        test_names: I,
+        summary: &mut RunSummary,
+    ) {
+        for test_name in test_names {
+            let result = TestResult::not_run(test_name, suite);
+            self.finished(&result);
+            summary.tests.push(result);
+        }
+    }
+
+    fn finished(&self, result: &TestResult) {
+        self.events.emit(RunEvent::TestFinished {
+            test_name: result.name.clone(),
+            status: result.status(),
+            timings: result.timings,
+        });
+    }
+
+    /// orders and filters the tests based on the outcome of the previous run
+    fn select(&mut self, mut tests: Tests) -> Vec<(String, Test)> {
+        let mut failed = Vec::new();
+        let mut warnings = Vec::new();
+        let failed_tests: Vec<String> = match &self.last_run {
+            Some(last_run) => self
+                .suites
+                .iter()
+                .flat_map(|suite| {
+                    last_run
+                        .failed(&suite.config_key())
+                        .map(move |test_name| suite.test_name(test_name))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        for test_name in failed_tests {
+            match tests.remove_entry(&test_name) {
+                Some(test) => failed.push(test),
+                None if self.selection != Selection::All => warnings.push(format!(
+                    "test '{}' failed in the previous run but no longer exists, ignoring it",
+                    test_name
+                )),
+                None => (),
+            }
+        }
+        for warning in warnings {
+            self.report(|reporter| reporter.warning(&warning));
+        }
+
+        if self.selection == Selection::Failed {
+            if failed.is_empty() {
+                self.report(|reporter| {
+                    reporter.warning("no test failed in the previous run, there is nothing to run")
+                });
+            }
+            return failed;
+        }
+
+        let mut rest: Vec<_> = tests.into_iter().collect();
+        if self.selection == Selection::All {
+            rest.append(&mut failed);
+        }
+        rest.sort_by(|(a, _), (b, _)| a.cmp(b));
+        failed.append(&mut rest);
+        failed
+    }
+
+    fn report<F: FnMut(&mut dyn Reporter)>(&mut self, mut f: F) {
+        for reporter in self.reporters.iter_mut() {
+            f(reporter.as_mut());
+        }
+    }
+}
+
+fn test_or_tests(amount: usize) -> &'static str {
+    if amount == 1 {
+        "test"
+    } else {
+        "tests"
+    }
+}
+
+/// the lines of the end of the run, empty when everything passed
+impl std::fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines = Vec::new();
+        let mut setup_not_run = 0;
+        for failure in &self.setup_failures {
+            if failure.phase == Phase::BeforeAll {
+                setup_not_run += failure.tests_not_run;
+                lines.push(format!(
+                    "Error: {} failed, {} {} not run",
+                    failure.name(),
+                    failure.tests_not_run,
+                    test_or_tests(failure.tests_not_run)
+                ));
+            }
+        }
+
+        let (passed, failed) = (self.passed(), self.failed());
+        let stopped = self.not_run() - setup_not_run;
+        if stopped != 0 {
+            lines.push(format!(
+                "Stopped after {} failed {}, {} {} not run",
+                failed,
+                test_or_tests(failed),
+                stopped,
+                test_or_tests(stopped)
+            ));
+        }
+        if failed != 0 {
+            lines.push(format!(
+                "Error: {} {} had errors out of {} {}",
+                failed,
+                test_or_tests(failed),
+                passed + failed,
+                test_or_tests(passed + failed)
+            ));
+        }
+        for failure in &self.setup_failures {
+            if failure.phase == Phase::AfterAll {
+                lines.push(format!("Error: {} failed", failure.name()));
+            }
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[test]
+fn runner_returns_a_summary() {
+    let source = "test:\n  works:\n    test: echo hi\n  breaks:\n    test: exit 1\n";
+    let data = crate::config::parse(
+        source,
+        std::path::Path::new("runner.yaml"),
+        crate::config::Format::Yaml,
+    )
+    .unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let summary = runtime
+        .block_on(Runner::new(data).with_passed_output(true).run())
+        .unwrap();
+
+    assert_eq!(1, summary.passed());
+    assert_eq!(1, summary.failed());
+    assert!(!summary.is_success());
+    let works = summary
+        .tests
+        .iter()
+        .find(|test| test.name == "works")
+        .unwrap();
+    assert_eq!(b"hi\n".to_vec(), works.output().unwrap().stdout.tail);
+    assert_eq!(
+        "Error: 1 test had errors out of 2 tests",
+        summary.to_string()
+    );
+}
+
+#[test]
+fn runner_sends_events() {
+    let source = "setup:\n  before_all: exit 0\ntest:\n  works:\n    test: printf hi\n";
+    let data = crate::config::parse(
+        source,
+        std::path::Path::new("runner.yaml"),
+        crate::config::Format::Yaml,
+    )
+    .unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let runner = Runner::new(data);
+    let events = runner.events();
+    let without_output = runner.events_without_output();
+    runtime.block_on(runner.run()).unwrap();
+    let mut events: Vec<RunEvent> = runtime.block_on(events.collect());
+    let without_output: Vec<RunEvent> = runtime.block_on(without_output.collect());
+
+    assert!(events.contains(&RunEvent::OutputChunk {
+        test_name: Some(String::from("works")),
+        phase: Some(Phase::Test),
+        stream: crate::output::Stream::Stdout,
+        data: b"hi".to_vec(),
+    }));
+
+    let timings = match events
+        .iter()
+        .find(|event| matches!(event, RunEvent::TestFinished { .. }))
+    {
+        Some(RunEvent::TestFinished { timings, .. }) => *timings,
+        _ => unreachable!(),
+    };
+    events.retain(|event| !matches!(event, RunEvent::OutputChunk { .. }));
+    let test_name = String::from("works");
+    assert_eq!(
+        vec![
+            RunEvent::TestQueued {
+                test_name: test_name.clone()
+            },
+            RunEvent::PhaseStarted {
+                test_name: None,
+                phase: Phase::BeforeAll
+            },
+            RunEvent::TestStarted {
+                test_name: test_name.clone()
+            },
+            RunEvent::PhaseStarted {
+                test_name: Some(test_name.clone()),
+                phase: Phase::Test
+            },
+            RunEvent::TestFinished {
+                test_name,
+                status: Status::Passed,
+                timings
+            },
+            RunEvent::SuiteFinished {
+                suite: None,
+                passed: 1,
+                failed: 0,
+                not_run: 0
+            },
+        ],
+        events
+    );
+    assert_eq!(events, without_output);
+}
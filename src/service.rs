@@ -0,0 +1,432 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use regex::{Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, timeout_at, Instant};
+
+use crate::command;
+use crate::output::{Captured, Tail};
+use crate::{Context, Error, SettingsStack};
+
+/// how much of the end of the log of a service goes into a failure report
+const REPORT_LIMIT: usize = 4 * 1024;
+/// time between two tries of a probe
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// time a service gets to stop after it is asked to, before it is killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// a command that runs in the background while the tests of the config run
+pub struct Service {
+    /// the command that starts it, it runs with the `command` of `global`
+    pub command: String,
+    /// variables on top of the `env` of `global`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// directory it runs in, relative to the directory the tests run in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// how to know that it is ready, without it the tests start right away
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ready: Option<Probe>,
+    /// miliseconds to wait until it is ready, default the `setup_timeout`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+}
+
+impl Service {
+    /// the keys of a service in a config file
+    pub const FIELDS: &[&str] = &["command", "env", "cwd", "ready", "timeout"];
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+/// a check that is tried until it passes
+pub enum Probe {
+    /// this port on localhost accepts connections
+    Tcp(u16),
+    /// a GET of this `http://` url answers with status 200
+    Http(String),
+    /// a line of the output of the service matches this regex
+    Log(String),
+    /// this command exits with 0
+    Command(String),
+}
+
+impl Probe {
+    /// checks that the url or the regex is valid
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            Probe::Http(url) => parse_url(url).map(|_| ()),
+            Probe::Log(regex) => log_regex(regex)
+                .map(|_| ())
+                .map_err(|e| format!("invalid regex `{}`: {}", regex, e)),
+            Probe::Tcp(_) | Probe::Command(_) => Ok(()),
+        }
+    }
+
+    /// tries the probe once
+    async fn passes(
+        &self,
+        log: &Mutex<Log>,
+        settings: &SettingsStack<'_, '_>,
+        ctx: &Context,
+    ) -> bool {
+        match self {
+            Probe::Tcp(port) => TcpStream::connect(("127.0.0.1", *port)).await.is_ok(),
+            Probe::Http(url) => match parse_url(url) {
+                Ok((host, path)) => http_status(&host, &path).await.ok() == Some(200),
+                Err(_) => false,
+            },
+            Probe::Log(regex) => match log_regex(regex) {
+                Ok(regex) => {
+                    let log = log.lock().expect("service log lock");
+                    regex.is_match(&String::from_utf8_lossy(&log.tail.last(usize::MAX)))
+                }
+                Err(_) => false,
+            },
+            Probe::Command(script) => command::run(script, settings, ctx).await.is_ok(),
+        }
+    }
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Tcp(port) => write!(f, "port {}", port),
+            Probe::Http(url) => write!(f, "GET {}", url),
+            Probe::Log(regex) => write!(f, "log line `{}`", regex),
+            Probe::Command(script) => write!(f, "command `{}`", script),
+        }
+    }
+}
+
+/// `^` and `$` match at the start and end of every line
+fn log_regex(regex: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(regex).multi_line(true).build()
+}
+
+/// the `host:port` and the path of an `http://host[:port][/path]` url
+fn parse_url(url: &str) -> Result<(String, String), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("`{}` is not an http:// url", url))?;
+    let (host, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(format!("`{}` has no host", url));
+    }
+
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    Ok((host, path.to_string()))
+}
+
+/// the status code of a GET request
+async fn http_status(host: &str, path: &str) -> io::Result<u16> {
+    let mut stream = TcpStream::connect(host).await?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    let mut buffer = [0; 1024];
+    while !response.contains(&b'\n') {
+        let length = stream.read(&mut buffer).await?;
+        if length == 0 {
+            break;
+        }
+        response.extend_from_slice(&buffer[..length]);
+    }
+
+    // HTTP/1.1 200 OK
+    String::from_utf8_lossy(&response)
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an http response"))
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// a service that did not start or did not get ready
+pub struct ServiceFailure {
+    pub name: String,
+    pub reason: String,
+    /// the end of its output
+    pub log: Captured,
+}
+
+impl std::fmt::Display for ServiceFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "service `{}` {}", self.name, self.reason)?;
+        write!(f, "log:\n{}", self.log)
+    }
+}
+
+/// the end of the output of a service, stdout and stderr together
+struct Log {
+    tail: Tail,
+    total: u64,
+}
+
+impl Log {
+    fn captured(&self, limit: usize) -> Captured {
+        Captured {
+            tail: self.tail.last(limit),
+            total: self.total,
+            log: None,
+        }
+    }
+}
+
+struct Started {
+    name: String,
+    child: Child,
+    /// the id of the process and its group, kept because `child` forgets it once it is reaped
+    pid: Option<u32>,
+    log: Arc<Mutex<Log>>,
+    /// the tasks that read the output into `log`
+    readers: Vec<JoinHandle<()>>,
+}
+
+#[derive(Clone, Default)]
+/// the services of a config that are started, they run until `stop_all`
+pub struct Services {
+    started: Arc<Mutex<Vec<Started>>>,
+}
+
+impl Services {
+    /// starts the services in order of their name, each one has to be ready before the next
+    /// one starts
+    pub async fn start_all(
+        &self,
+        services: &BTreeMap<String, Service>,
+        settings: &SettingsStack<'_, '_>,
+        ctx: &Context,
+    ) -> Result<(), Error> {
+        for (name, service) in services {
+            self.start(name, service, settings, ctx).await?;
+        }
+        Ok(())
+    }
+
+    /// starts the service and waits until its probe passes. It is stopped by `stop_all`, also
+    /// when it did not get ready
+    pub async fn start(
+        &self,
+        name: &str,
+        service: &Service,
+        settings: &SettingsStack<'_, '_>,
+        ctx: &Context,
+    ) -> Result<(), Error> {
+        let argv = command::argv(&service.command, settings);
+        let mut command = std::process::Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        if settings.clear_env() {
+            command.env_clear();
+        }
+        command.envs(settings.env()).envs(&service.env);
+        let cwd = ctx
+            .cwd()
+            .unwrap_or_else(|| Path::new(""))
+            .join(service.cwd.as_deref().unwrap_or_else(|| Path::new("")));
+        if !cwd.as_os_str().is_empty() {
+            command.current_dir(cwd);
+        }
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // a group of its own, so stopping it also stops the processes it started
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut command = Command::from(command);
+        command.kill_on_drop(true);
+
+        let log = Arc::new(Mutex::new(Log {
+            tail: Tail::new(settings.output_limit() as usize * 1024),
+            total: 0,
+        }));
+        let mut child = command.spawn().map_err(|e| {
+            Error::Service(ServiceFailure {
+                name: name.to_string(),
+                reason: format!("can not start: {}", e),
+                log: Captured::default(),
+            })
+        })?;
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(tokio::spawn(collect(stdout, log.clone())));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(tokio::spawn(collect(stderr, log.clone())));
+        }
+        self.started.lock().expect("services lock").push(Started {
+            name: name.to_string(),
+            pid: child.id(),
+            child,
+            log: log.clone(),
+            readers,
+        });
+
+        match &service.ready {
+            Some(probe) => {
+                let timeout = service.timeout.unwrap_or_else(|| settings.setup_timeout());
+                self.wait_until_ready(name, probe, timeout, &log, settings, ctx)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn wait_until_ready(
+        &self,
+        name: &str,
+        probe: &Probe,
+        timeout: u32,
+        log: &Mutex<Log>,
+        settings: &SettingsStack<'_, '_>,
+        ctx: &Context,
+    ) -> Result<(), Error> {
+        let fail = |reason: String| {
+            let log = log.lock().expect("service log lock").captured(REPORT_LIMIT);
+            Err(Error::Service(ServiceFailure {
+                name: name.to_string(),
+                reason,
+                log,
+            }))
+        };
+        let deadline = Instant::now() + Duration::from_millis(timeout.into());
+
+        loop {
+            if let Some(status) = self.exited(name) {
+                self.read_the_rest(name).await;
+                return fail(format!("stopped with {} before it was ready", status));
+            }
+            let passes = tokio::select! {
+                passes = timeout_at(deadline, probe.passes(log, settings, ctx)) => {
+                    passes.unwrap_or(false)
+                }
+                _ = ctx.cancelled() => return Err(Error::Cancelled),
+            };
+            if passes {
+                return Ok(());
+            }
+            if Instant::now() + POLL_INTERVAL >= deadline {
+                return fail(format!(
+                    "is not ready after {} ms, waited for the {}",
+                    timeout, probe
+                ));
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// the exit status when the service stopped by itself
+    fn exited(&self, name: &str) -> Option<std::process::ExitStatus> {
+        let mut started = self.started.lock().expect("services lock");
+        let service = started.iter_mut().find(|service| service.name == name)?;
+        service.child.try_wait().ok().flatten()
+    }
+
+    /// waits a moment for the output a service wrote before it exited, the processes it
+    /// started can keep the output open
+    async fn read_the_rest(&self, name: &str) {
+        let readers = match self
+            .started
+            .lock()
+            .expect("services lock")
+            .iter_mut()
+            .find(|service| service.name == name)
+        {
+            Some(service) => std::mem::take(&mut service.readers),
+            None => return,
+        };
+        let deadline = Instant::now() + POLL_INTERVAL;
+        for reader in readers {
+            let _ = timeout_at(deadline, reader).await;
+        }
+    }
+
+    /// the end of the log of every service, for the failure reports of the tests
+    pub fn logs(&self) -> Vec<(String, Captured)> {
+        self.started
+            .lock()
+            .expect("services lock")
+            .iter()
+            .map(|service| {
+                let log = service.log.lock().expect("service log lock");
+                (service.name.clone(), log.captured(REPORT_LIMIT))
+            })
+            .collect()
+    }
+
+    /// stops the services, the last one that started first
+    pub async fn stop_all(&self) {
+        let started = std::mem::take(&mut *self.started.lock().expect("services lock"));
+        for mut service in started.into_iter().rev() {
+            stop(&mut service).await;
+        }
+    }
+}
+
+async fn collect<R: AsyncRead + Unpin>(mut reader: R, log: Arc<Mutex<Log>>) {
+    let mut buffer = [0; 4096];
+    while let Ok(length @ 1..) = reader.read(&mut buffer).await {
+        let mut log = log.lock().expect("service log lock");
+        log.tail.extend(&buffer[..length]);
+        log.total += length as u64;
+    }
+}
+
+/// asks the process group of the service to stop, what is left of it when the service exited
+/// or after a while is killed. The group is signalled also when the service itself already
+/// exited, the processes it started can still be running
+#[cfg(unix)]
+async fn stop(service: &mut Started) {
+    if let Some(pid) = service.pid {
+        let group = -(pid as libc::pid_t);
+        // SAFETY: kill only sends a signal, the group is the one of the service
+        unsafe { libc::kill(group, libc::SIGTERM) };
+        let _ = timeout(STOP_TIMEOUT, service.child.wait()).await;
+        // SAFETY: as above, the processes the service started are killed as well
+        unsafe { libc::kill(group, libc::SIGKILL) };
+    }
+    let _ = service.child.kill().await;
+}
+
+#[cfg(not(unix))]
+async fn stop(service: &mut Started) {
+    let _ = service.child.kill().await;
+}
+
+#[test]
+fn parse_url_splits_host_and_path() {
+    assert_eq!(
+        Ok((String::from("localhost:8080"), String::from("/health"))),
+        parse_url("http://localhost:8080/health")
+    );
+    assert_eq!(
+        Ok((String::from("localhost:80"), String::from("/"))),
+        parse_url("http://localhost")
+    );
+    assert!(parse_url("https://localhost").is_err());
+    assert!(Probe::Log(String::from("[")).check().is_err());
+}
@@ -1,8 +1,13 @@
 use std::collections::HashMap;
 
 use derive_more::Deref;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::executor::ExecutorConfig;
+use crate::output::ShowOutput;
+use crate::snapshot::SnapshotFilter;
+
 pub struct SettingsStack<'a, 'b> {
     root: &'a Settings,
     layer: &'b [&'b Settings],
@@ -49,7 +54,7 @@ impl<'a, 'b> SettingsStack<'a, 'b> {
         self.root.clear_env()
     }
 
-    pub fn command_with_args(&self) -> (String, shlex::Shlex) {
+    pub fn command_with_args(&self) -> (String, shlex::Shlex<'_>) {
         let (program, program_args) = {
             let mut program_args = shlex::Shlex::new(self.command());
             match program_args.next() {
@@ -75,9 +80,80 @@ impl<'a, 'b> SettingsStack<'a, 'b> {
         self.root.should_fail()
     }
 
-    pub fn env(&'a self) -> Box<dyn Iterator<Item = (&String, &String)> + 'a> {
+    pub fn slow_threshold(&self) -> Option<u32> {
+        for layer in self.layer {
+            if let Some(slow_threshold) = layer.slow_threshold {
+                return Some(slow_threshold);
+            }
+        }
+
+        self.root.slow_threshold()
+    }
+
+    pub fn show_output(&self) -> ShowOutput {
+        for layer in self.layer {
+            if let Some(show_output) = layer.show_output {
+                return show_output;
+            }
+        }
+
+        self.root.show_output()
+    }
+
+    /// in KB, at least 1 so the output is still split in lines
+    pub fn output_limit(&self) -> u32 {
+        for layer in self.layer {
+            if let Some(output_limit) = layer.output_limit {
+                return output_limit.max(1);
+            }
+        }
+
+        self.root.output_limit().max(1)
+    }
+
+    pub fn executor(&self) -> &ExecutorConfig {
+        for layer in self.layer {
+            if let Some(ref executor) = layer.executor {
+                return executor;
+            }
+        }
+
+        self.root.executor()
+    }
+
+    pub fn image(&self) -> Option<&str> {
+        for layer in self.layer {
+            if let Some(ref image) = layer.image {
+                return Some(image);
+            }
+        }
+
+        self.root.image()
+    }
+
+    pub fn reuse_container(&self) -> bool {
+        for layer in self.layer {
+            if let Some(reuse_container) = layer.reuse_container {
+                return reuse_container;
+            }
+        }
+
+        self.root.reuse_container()
+    }
+
+    pub fn snapshot_filters(&self) -> &[SnapshotFilter] {
+        for layer in self.layer {
+            if let Some(ref snapshot_filters) = layer.snapshot_filters {
+                return snapshot_filters;
+            }
+        }
+
+        self.root.snapshot_filters()
+    }
+
+    pub fn env(&'a self) -> Box<dyn Iterator<Item = (&'a String, &'a String)> + 'a> {
         let mut iter: Box<dyn Iterator<Item = (&String, &String)>> =
-            Box::new(self.root.env().into_iter());
+            Box::new(self.root.env().iter());
         for layer in self.layer {
             iter = Box::new(iter.chain(layer.env()))
         }
@@ -92,29 +168,78 @@ impl<'a, 'b> SettingsStack<'a, 'b> {
             command: Some(self.command().to_string()),
             clear_env: Some(self.clear_env()),
             should_fail: Some(self.should_fail()),
+            slow_threshold: self.slow_threshold(),
+            show_output: Some(self.show_output()),
+            output_limit: Some(self.output_limit()),
+            executor: Some(self.executor().clone()),
+            image: self.image().map(String::from),
+            reuse_container: Some(self.reuse_container()),
+            snapshot_filters: Some(self.snapshot_filters().to_vec()),
             env: self.env().map(|(k, v)| (k.clone(), v.clone())).collect(),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Settings {
     /// timeout in miliseconds, default 5000 ms
+    #[schemars(range(min = 1))]
     timeout: Option<u32>,
     /// setup timeout in miliseconds, default 5000 ms
+    #[schemars(range(min = 1))]
     setup_timeout: Option<u32>,
     /// command to execute the tests with, default "sh -c"
     command: Option<String>,
     /// clear the enviroment variables before executing the command, default false
     clear_env: Option<bool>,
-    /// mark that the test should fail
+    /// mark that the test should fail, `before` and `after` still have to succeed
     should_fail: Option<bool>,
+    /// warn when a test takes longer than this amount of miliseconds, the test does not fail
+    #[serde(alias = "warn_after", skip_serializing_if = "Option::is_none")]
+    slow_threshold: Option<u32>,
+    /// when to show the output of the test: always (while it runs), on_failure or never, default on_failure
+    show_output: Option<ShowOutput>,
+    /// amount of kilobytes of the end of the output kept for the failure message, default 64 KB
+    output_limit: Option<u32>,
+    /// where the commands run: local, docker (in a running container), ssh or mock, default local
+    executor: Option<ExecutorConfig>,
+    /// run the commands in a new container of this image with docker or podman
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+    /// start one container of the `image` for all the tests of the config, default false
+    reuse_container: Option<bool>,
+    /// regex replacements in the output before it is compared to the `snapshot`, for example
+    /// for timestamps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    snapshot_filters: Option<Vec<SnapshotFilter>>,
     /// Add env
     #[serde(default)]
     env: HashMap<String, String>,
 }
 
 impl Settings {
+    /// the keys of the settings in a config file, used to find typos
+    pub const FIELDS: &[&str] = &[
+        "timeout",
+        "setup_timeout",
+        "command",
+        "clear_env",
+        "should_fail",
+        "slow_threshold",
+        "warn_after",
+        "show_output",
+        "output_limit",
+        "executor",
+        "image",
+        "reuse_container",
+        "snapshot_filters",
+        "env",
+    ];
+
+    pub fn builder() -> SettingsBuilder {
+        SettingsBuilder::default()
+    }
+
     pub fn stack<'a, 'b>(&'a self, other: &'b [&Settings]) -> SettingsStack<'a, 'b> {
         SettingsStack {
             root: self,
@@ -162,6 +287,47 @@ impl Settings {
         false
     }
 
+    pub fn slow_threshold(&self) -> Option<u32> {
+        self.slow_threshold
+    }
+
+    pub fn show_output(&self) -> ShowOutput {
+        if let Some(show_output) = self.show_output {
+            return show_output;
+        }
+
+        ShowOutput::default()
+    }
+
+    pub fn output_limit(&self) -> u32 {
+        if let Some(output_limit) = self.output_limit {
+            return output_limit;
+        }
+
+        64
+    }
+
+    pub fn executor(&self) -> &ExecutorConfig {
+        static LOCAL: ExecutorConfig = ExecutorConfig::Local;
+        self.executor.as_ref().unwrap_or(&LOCAL)
+    }
+
+    pub fn image(&self) -> Option<&str> {
+        self.image.as_deref()
+    }
+
+    pub fn reuse_container(&self) -> bool {
+        if let Some(reuse_container) = self.reuse_container {
+            return reuse_container;
+        }
+
+        false
+    }
+
+    pub fn snapshot_filters(&self) -> &[SnapshotFilter] {
+        self.snapshot_filters.as_deref().unwrap_or_default()
+    }
+
     pub fn env(&self) -> &HashMap<String, String> {
         &self.env
     }
@@ -179,12 +345,115 @@ impl Settings {
             command: Some(self.command().to_string()),
             clear_env: Some(self.clear_env()),
             should_fail: Some(self.should_fail()),
+            slow_threshold: self.slow_threshold(),
+            show_output: Some(self.show_output()),
+            output_limit: Some(self.output_limit()),
+            executor: Some(self.executor().clone()),
+            image: self.image.clone(),
+            reuse_container: Some(self.reuse_container()),
+            snapshot_filters: self.snapshot_filters.clone(),
             env,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Default, Deref, Serialize, Deserialize)]
+#[derive(Debug, Default)]
+/// builds `Settings` in code, the settings that are not set keep their default
+pub struct SettingsBuilder {
+    settings: Settings,
+}
+
+impl SettingsBuilder {
+    /// in miliseconds
+    pub fn timeout(mut self, timeout: u32) -> SettingsBuilder {
+        self.settings.timeout = Some(timeout);
+        self
+    }
+
+    /// in miliseconds
+    pub fn setup_timeout(mut self, setup_timeout: u32) -> SettingsBuilder {
+        self.settings.setup_timeout = Some(setup_timeout);
+        self
+    }
+
+    pub fn command<S: Into<String>>(mut self, command: S) -> SettingsBuilder {
+        self.settings.command = Some(command.into());
+        self
+    }
+
+    pub fn clear_env(mut self, clear_env: bool) -> SettingsBuilder {
+        self.settings.clear_env = Some(clear_env);
+        self
+    }
+
+    pub fn should_fail(mut self, should_fail: bool) -> SettingsBuilder {
+        self.settings.should_fail = Some(should_fail);
+        self
+    }
+
+    /// in miliseconds
+    pub fn slow_threshold(mut self, slow_threshold: u32) -> SettingsBuilder {
+        self.settings.slow_threshold = Some(slow_threshold);
+        self
+    }
+
+    pub fn show_output(mut self, show_output: ShowOutput) -> SettingsBuilder {
+        self.settings.show_output = Some(show_output);
+        self
+    }
+
+    /// in kilobytes
+    pub fn output_limit(mut self, output_limit: u32) -> SettingsBuilder {
+        self.settings.output_limit = Some(output_limit);
+        self
+    }
+
+    pub fn executor(mut self, executor: ExecutorConfig) -> SettingsBuilder {
+        self.settings.executor = Some(executor);
+        self
+    }
+
+    pub fn image<S: Into<String>>(mut self, image: S) -> SettingsBuilder {
+        self.settings.image = Some(image.into());
+        self
+    }
+
+    pub fn reuse_container(mut self, reuse_container: bool) -> SettingsBuilder {
+        self.settings.reuse_container = Some(reuse_container);
+        self
+    }
+
+    /// adds a filter for the snapshots, can be called more than once
+    pub fn snapshot_filter<R: Into<String>, S: Into<String>>(
+        mut self,
+        regex: R,
+        replacement: S,
+    ) -> SettingsBuilder {
+        self.settings
+            .snapshot_filters
+            .get_or_insert_with(Vec::new)
+            .push(SnapshotFilter::new(regex, replacement));
+        self
+    }
+
+    /// adds an environment variable, can be called more than once
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> SettingsBuilder {
+        self.settings.env.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn build(self) -> Settings {
+        self.settings
+    }
+}
+
+impl From<Settings> for SettingsBuilder {
+    fn from(settings: Settings) -> SettingsBuilder {
+        SettingsBuilder { settings }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Deref, Serialize, Deserialize, JsonSchema)]
 pub struct GlobalSettings {
     /// Amount of test that run at the same time. You can increase this to speed up the tests if your processor can handle it. Or lower it if you computer freezes while running the tests, default 64.
     max_test_concurrency: Option<usize>,
@@ -194,6 +463,13 @@ pub struct GlobalSettings {
 }
 
 impl GlobalSettings {
+    /// the keys in a config file next to the `Settings::FIELDS`
+    pub const FIELDS: &[&str] = &["max_test_concurrency"];
+
+    pub fn builder() -> GlobalSettingsBuilder {
+        GlobalSettingsBuilder::default()
+    }
+
     pub fn max_test_concurrency(&self) -> usize {
         if let Some(max_test_concurrency) = self.max_test_concurrency {
             return max_test_concurrency;
@@ -209,3 +485,47 @@ impl GlobalSettings {
         }
     }
 }
+
+#[derive(Debug, Default)]
+/// builds `GlobalSettings` in code
+pub struct GlobalSettingsBuilder {
+    global: GlobalSettings,
+}
+
+impl GlobalSettingsBuilder {
+    pub fn max_test_concurrency(mut self, max_test_concurrency: usize) -> GlobalSettingsBuilder {
+        self.global.max_test_concurrency = Some(max_test_concurrency);
+        self
+    }
+
+    /// the settings for all the tests, see `Settings::builder`
+    pub fn settings(mut self, settings: Settings) -> GlobalSettingsBuilder {
+        self.global.other_settings = settings;
+        self
+    }
+
+    pub fn build(self) -> GlobalSettings {
+        self.global
+    }
+}
+
+#[test]
+fn builder_sets_only_the_given_settings() {
+    let settings = Settings::builder()
+        .timeout(100)
+        .env("A", "1")
+        .env("B", "2")
+        .build();
+
+    assert_eq!(100, settings.timeout());
+    assert_eq!(5000, settings.setup_timeout());
+    assert_eq!(2, settings.env().len());
+    assert_eq!(None, settings.command);
+
+    let global = GlobalSettings::builder()
+        .max_test_concurrency(2)
+        .settings(settings)
+        .build();
+    assert_eq!(2, global.max_test_concurrency());
+    assert_eq!(100, global.timeout());
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::Tests;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// one part of the test suite, `index` is one based: `1/3`, `2/3` and `3/3`
+pub struct Shard {
+    pub index: usize,
+    pub total: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Shard, String> {
+        let error = || format!("invalid shard '{}', expected something like '1/3'", input);
+        let (index, total) = input.split_once('/').ok_or_else(error)?;
+        let index: usize = index.trim().parse().map_err(|_| error())?;
+        let total: usize = total.trim().parse().map_err(|_| error())?;
+
+        if index == 0 || total == 0 || index > total {
+            return Err(error());
+        }
+
+        Ok(Shard { index, total })
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.total)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// how the tests are divided over the shards
+pub enum Strategy {
+    /// by a stable hash of the test name
+    Hash,
+    /// balance the durations recorded in a shared `last-run.json`
+    Duration,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Strategy, String> {
+        match input {
+            "hash" => Ok(Strategy::Hash),
+            "duration" => Ok(Strategy::Duration),
+            _ => Err(format!(
+                "invalid shard strategy '{}', expected 'hash' or 'duration'",
+                input
+            )),
+        }
+    }
+}
+
+/// FNV-1a, the hash should not change between baret versions or machines
+pub fn stable_hash(input: &str) -> u64 {
+    input.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// the one based shard index for every test, `durations` are the recorded durations by the
+/// name of the test in the run, for `Strategy::Duration`
+pub fn assign_tests(
+    tests: &Tests,
+    total: usize,
+    strategy: Strategy,
+    durations: &HashMap<String, Duration>,
+) -> HashMap<String, usize> {
+    let tests = tests
+        .keys()
+        .map(|test_name| (test_name.as_str(), durations.get(test_name).copied()));
+
+    assign(tests, total, strategy)
+        .into_iter()
+        .map(|(test_name, shard)| (test_name.to_string(), shard))
+        .collect()
+}
+
+/// returns the one based shard index for every test. `Strategy::Duration` balances the tests
+/// with a recorded duration, the others are divided by the hash. Machines with the same
+/// durations pick the same tests
+pub fn assign<'a, I>(tests: I, total: usize, strategy: Strategy) -> HashMap<&'a str, usize>
+where
+    I: IntoIterator<Item = (&'a str, Option<Duration>)>,
+{
+    let mut known = Vec::new();
+    let mut assignment = HashMap::new();
+    for (name, duration) in tests {
+        match (strategy, duration) {
+            (Strategy::Duration, Some(duration)) => known.push((name, duration)),
+            _ => {
+                assignment.insert(name, (stable_hash(name) % total as u64) as usize + 1);
+            }
+        }
+    }
+
+    assignment.extend(by_duration(known, total));
+    assignment
+}
+
+/// a warning for `Strategy::Duration` when some of the tests have no recorded duration
+pub fn missing_durations(tests: &Tests, durations: &HashMap<String, Duration>) -> Option<String> {
+    let missing = tests
+        .keys()
+        .filter(|test_name| !durations.contains_key(*test_name))
+        .count();
+    if missing == 0 {
+        return None;
+    }
+
+    Some(format!(
+        "{} of {} tests have no recorded duration, they are divided over the shards by their name",
+        missing,
+        tests.len()
+    ))
+}
+
+/// greedily gives the longest test to the shard with the least work
+fn by_duration(mut tests: Vec<(&str, Duration)>, total: usize) -> HashMap<&str, usize> {
+    tests.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+
+    let mut load = vec![Duration::default(); total];
+    let mut assignment = HashMap::new();
+    for (name, duration) in tests {
+        let (shard, _) = load
+            .iter()
+            .enumerate()
+            .min_by_key(|(index, load)| (**load, *index))
+            .unwrap();
+        load[shard] += duration;
+        assignment.insert(name, shard + 1);
+    }
+
+    assignment
+}
+
+#[test]
+fn parse_shard() {
+    assert_eq!(Ok(Shard { index: 2, total: 3 }), "2/3".parse());
+    assert!("0/3".parse::<Shard>().is_err());
+    assert!("4/3".parse::<Shard>().is_err());
+    assert!("3".parse::<Shard>().is_err());
+}
+
+#[test]
+fn hash_assignment_is_stable() {
+    let tests = vec![("test 1", None), ("test 2", None), ("test 3", None)];
+
+    let first = assign(tests.clone(), 2, Strategy::Hash);
+    let second = assign(tests.into_iter().rev(), 2, Strategy::Hash);
+
+    assert_eq!(first, second);
+    assert!(first.values().all(|shard| (1..=2).contains(shard)));
+}
+
+#[test]
+fn duration_assignment_balances_shards() {
+    let ms = |ms| Some(Duration::from_millis(ms));
+    let tests = vec![("a", ms(100)), ("b", ms(60)), ("c", ms(50)), ("d", ms(10))];
+
+    let assignment = assign(tests, 2, Strategy::Duration);
+
+    assert_eq!(assignment["a"], 1);
+    assert_eq!(assignment["d"], 1);
+    assert_eq!(assignment["b"], 2);
+    assert_eq!(assignment["c"], 2);
+}
+
+#[test]
+fn duration_assignment_hashes_tests_without_duration() {
+    let ms = |ms| Some(Duration::from_millis(ms));
+    let tests = vec![("a", ms(100)), ("b", None), ("c", ms(50))];
+
+    let assignment = assign(tests.clone(), 2, Strategy::Duration);
+
+    assert_eq!(assignment["a"], 1);
+    assert_eq!(assignment["c"], 2);
+    assert_eq!(assignment["b"], assign(tests, 2, Strategy::Hash)["b"]);
+}
@@ -0,0 +1,385 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use tokio::io;
+
+use crate::context;
+use crate::output::{CommandOutput, ShowOutput};
+use crate::{Context, Error, SettingsStack};
+
+/// the directory next to the config with the snapshots of its tests
+pub const SNAPSHOT_DIR: &str = "__snapshots__";
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+/// `true` to compare the output to `__snapshots__/<test>.snap` next to the config, or the
+/// path of the snapshot relative to the config
+pub enum Snapshot {
+    Enabled(bool),
+    Path(PathBuf),
+}
+
+impl Snapshot {
+    pub fn is_enabled(&self) -> bool {
+        *self != Snapshot::Enabled(false)
+    }
+
+    /// the file of the snapshot relative to the directory of the config, `None` when it is
+    /// turned off
+    pub fn path(&self, test_name: &str) -> Option<PathBuf> {
+        match self {
+            Snapshot::Enabled(false) => None,
+            Snapshot::Enabled(true) => Some(default_path(test_name)),
+            Snapshot::Path(path) => Some(path.clone()),
+        }
+    }
+}
+
+/// `__snapshots__/<test>.snap`, the characters that do not belong in a file name are replaced
+/// by `_` and then a hash of the name is added: `logs in` is in `logs_in-93500bbd.snap`
+pub fn default_path(test_name: &str) -> PathBuf {
+    Path::new(SNAPSHOT_DIR).join(format!("{}.snap", context::unique_file_name(test_name)))
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// replaces every match of `regex` in the output, `$1` in the replacement is the first group
+pub struct SnapshotFilter {
+    pub regex: String,
+    pub replacement: String,
+}
+
+impl SnapshotFilter {
+    pub fn new<R: Into<String>, S: Into<String>>(regex: R, replacement: S) -> SnapshotFilter {
+        SnapshotFilter {
+            regex: regex.into(),
+            replacement: replacement.into(),
+        }
+    }
+
+    /// checks that the regex is valid
+    pub fn check(&self) -> Result<(), String> {
+        self.compile().map(|_| ())
+    }
+
+    fn compile(&self) -> Result<Regex, String> {
+        Regex::new(&self.regex).map_err(|e| format!("invalid regex `{}`: {}", self.regex, e))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+/// the output does not match the snapshot, or the snapshot could not be used
+pub struct SnapshotFailure {
+    pub path: PathBuf,
+    pub reason: String,
+    /// unified diff from the snapshot to the output
+    pub diff: Option<String>,
+}
+
+impl std::fmt::Display for SnapshotFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "snapshot {}: {}", self.path.display(), self.reason)?;
+        if let Some(diff) = &self.diff {
+            write!(f, "{}", diff)?;
+        }
+        Ok(())
+    }
+}
+
+/// a snapshot file: which test of which config it belongs to and the output
+#[derive(Debug, PartialEq, Clone)]
+struct SnapshotFile {
+    config: String,
+    test: String,
+    body: String,
+}
+
+impl SnapshotFile {
+    fn parse(text: &str) -> Option<SnapshotFile> {
+        // the body starts at the first `--- <name>` line, the header is above it
+        let start = if text.starts_with("--- ") {
+            0
+        } else {
+            text.find("\n--- ")? + 1
+        };
+        let mut config = None;
+        let mut test = None;
+        for line in text[..start].lines() {
+            if let Some(value) = line.strip_prefix("config: ") {
+                config = Some(value.to_string());
+            } else if let Some(value) = line.strip_prefix("test: ") {
+                test = Some(value.to_string());
+            }
+        }
+
+        Some(SnapshotFile {
+            config: config?,
+            test: test?,
+            body: text[start..].to_string(),
+        })
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "config: {}\ntest: {}\n{}",
+            self.config, self.test, self.body
+        )
+    }
+}
+
+/// the `snapshot_filters` of the settings with their regexes compiled
+fn filters<'a>(settings: &'a SettingsStack<'_, '_>) -> Result<Vec<(Regex, &'a str)>, String> {
+    settings
+        .snapshot_filters()
+        .iter()
+        .map(|filter| Ok((filter.compile()?, filter.replacement.as_str())))
+        .collect()
+}
+
+/// marks text that does not end with a newline, so `printf one` and `echo one` differ
+const NO_NEWLINE: &str = "\\ no newline at the end";
+
+/// a part of the body: `--- <name>` and the text after the filters
+fn section(name: &str, bytes: &[u8], filters: &[(Regex, &str)]) -> String {
+    let mut text = String::from_utf8_lossy(bytes).to_string();
+    for (regex, replacement) in filters {
+        text = regex.replace_all(&text, *replacement).to_string();
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+        text.push_str(NO_NEWLINE);
+        text.push('\n');
+    }
+    format!("--- {}\n{}", name, text)
+}
+
+/// stdout and stderr of the command after the filters
+fn body(output: &CommandOutput, filters: &[(Regex, &str)]) -> String {
+    format!(
+        "{}{}",
+        section("stdout", &output.stdout.tail, filters),
+        section("stderr", &output.stderr.tail, filters)
+    )
+}
+
+/// `__snapshots__/<test>.<file>.snap`, the snapshot of a file the test made
+pub fn file_path(test_name: &str, file: &Path) -> PathBuf {
+    Path::new(SNAPSHOT_DIR).join(format!(
+        "{}.{}.snap",
+        context::unique_file_name(test_name),
+        context::unique_file_name(&file.to_string_lossy())
+    ))
+}
+
+fn failure(path: &Path, reason: String) -> Error {
+    Error::Snapshot(SnapshotFailure {
+        path: path.to_path_buf(),
+        reason,
+        diff: None,
+    })
+}
+
+/// compares the output of the test to its snapshot, with `--update-snapshots` the snapshot is
+/// written instead. A test with `show_output: never` has no output to compare and fails
+pub async fn check(
+    snapshot: &Snapshot,
+    output: &CommandOutput,
+    settings: &SettingsStack<'_, '_>,
+    ctx: &Context,
+) -> Result<(), Error> {
+    let test_name = ctx.local_test_name().unwrap_or_default();
+    let path = match snapshot.path(test_name) {
+        Some(path) => ctx.config_dir().unwrap_or_else(|| Path::new("")).join(path),
+        None => return Ok(()),
+    };
+
+    if settings.show_output() == ShowOutput::Never {
+        return Err(failure(
+            &path,
+            String::from("`show_output: never` leaves no output to compare"),
+        ));
+    }
+    if output.stdout.is_truncated() || output.stderr.is_truncated() {
+        return Err(failure(
+            &path,
+            String::from("the output is longer than `output_limit`, raise it to compare all of it"),
+        ));
+    }
+    let filters = filters(settings).map_err(|e| failure(&path, e))?;
+    compare(&path, body(output, &filters), ctx).await
+}
+
+/// compares a file the test made, relative to the directory the test ran in, to its snapshot
+/// in `__snapshots__/<test>.<file>.snap`
+pub async fn check_file(
+    file: &Path,
+    settings: &SettingsStack<'_, '_>,
+    ctx: &Context,
+) -> Result<(), Error> {
+    let test_name = ctx.local_test_name().unwrap_or_default();
+    let path = ctx
+        .config_dir()
+        .unwrap_or_else(|| Path::new(""))
+        .join(file_path(test_name, file));
+
+    let content = tokio::fs::read(ctx.cwd().unwrap_or_else(|| Path::new("")).join(file))
+        .await
+        .map_err(|e| failure(&path, format!("unable to read {}: {}", file.display(), e)))?;
+    let filters = filters(settings).map_err(|e| failure(&path, e))?;
+    compare(
+        &path,
+        section(&file.to_string_lossy(), &content, &filters),
+        ctx,
+    )
+    .await
+}
+
+/// compares `body` to the snapshot at `path` and writes it when the snapshots are updated
+async fn compare(path: &Path, body: String, ctx: &Context) -> Result<(), Error> {
+    let actual = SnapshotFile {
+        config: ctx.config_name().unwrap_or_default().to_string(),
+        test: ctx.local_test_name().unwrap_or_default().to_string(),
+        body,
+    };
+
+    let stored = match tokio::fs::read_to_string(path).await {
+        Ok(text) => match SnapshotFile::parse(&text) {
+            None if !ctx.update_snapshots() => {
+                return Err(failure(
+                    path,
+                    String::from("it is not a snapshot, run with --update-snapshots to replace it"),
+                ))
+            }
+            stored => stored,
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound && ctx.update_snapshots() => None,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(failure(
+                path,
+                String::from("no snapshot yet, run with --update-snapshots to write it"),
+            ))
+        }
+        Err(e) => return Err(failure(path, e.to_string())),
+    };
+    if let Some(stored) = stored {
+        if (&stored.config, &stored.test) != (&actual.config, &actual.test) {
+            return Err(failure(
+                path,
+                format!(
+                    "it belongs to `{}` in {}, give this test its own with `snapshot: <path>`",
+                    stored.test, stored.config
+                ),
+            ));
+        }
+        if stored.body == actual.body {
+            return Ok(());
+        }
+        if !ctx.update_snapshots() {
+            let diff = TextDiff::from_lines(&stored.body, &actual.body)
+                .unified_diff()
+                .header("snapshot", "output")
+                .to_string();
+            return Err(Error::Snapshot(SnapshotFailure {
+                path: path.to_path_buf(),
+                reason: String::from(
+                    "the output changed, run with --update-snapshots to accept it",
+                ),
+                diff: Some(diff),
+            }));
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, actual.render()).await?;
+    Ok(())
+}
+
+/// the snapshots in `__snapshots__` of `config_dir` that belong to the config, but are not
+/// one of the `expected` snapshots of its tests
+pub fn orphans<I>(config_dir: &Path, config_name: &str, expected: I) -> Vec<PathBuf>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    let expected: HashSet<PathBuf> = expected
+        .into_iter()
+        .map(|path| config_dir.join(path))
+        .collect();
+    let entries = match std::fs::read_dir(config_dir.join(SNAPSHOT_DIR)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut orphans: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "snap")
+        })
+        .filter(|path| !expected.contains(path))
+        .filter(|path| {
+            std::fs::read_to_string(path)
+                .ok()
+                .and_then(|text| SnapshotFile::parse(&text))
+                .is_some_and(|snapshot| snapshot.config == config_name)
+        })
+        .collect();
+    orphans.sort();
+    orphans
+}
+
+#[test]
+fn snapshot_file_round_trips() {
+    let snapshot = SnapshotFile {
+        config: String::from("api.yaml"),
+        test: String::from("logs in"),
+        body: String::from("--- stdout\nhallo\n--- stderr\n"),
+    };
+
+    assert_eq!(
+        Some(snapshot.clone()),
+        SnapshotFile::parse(&snapshot.render())
+    );
+    assert_eq!(
+        PathBuf::from("__snapshots__/logs_in-93500bbd.snap"),
+        Snapshot::Enabled(true).path("logs in").unwrap()
+    );
+    assert_eq!(None, Snapshot::Enabled(false).path("logs in"));
+}
+
+#[test]
+fn filters_replace_the_output() {
+    let output = CommandOutput {
+        status: crate::executor::exit_status(0),
+        stdout: crate::output::Captured {
+            tail: b"started at 12:01:02 in /tmp/abc123".to_vec(),
+            total: 34,
+            log: None,
+        },
+        stderr: crate::output::Captured::default(),
+    };
+    let filters = [
+        (Regex::new(r"\d{2}:\d{2}:\d{2}").unwrap(), "[TIME]"),
+        (Regex::new(r"/tmp/\w+").unwrap(), "[TMP]"),
+    ];
+
+    assert_eq!(
+        "--- stdout\nstarted at [TIME] in [TMP]\n\\ no newline at the end\n--- stderr\n",
+        body(&output, &filters)
+    );
+}
+
+#[test]
+fn sections_keep_the_last_newline() {
+    assert_eq!("--- stdout\none\n", section("stdout", b"one\n", &[]));
+    assert_eq!(
+        "--- stdout\none\n\\ no newline at the end\n",
+        section("stdout", b"one", &[])
+    );
+    assert_eq!("--- stdout\n", section("stdout", b"", &[]));
+}
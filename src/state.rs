@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::suite::Suite;
+
+/// directory where baret keeps the state between runs
+pub const STATE_DIR: &str = ".baret";
+/// file inside the `STATE_DIR` with the outcome of the previous run
+pub const LAST_RUN_FILE: &str = "last-run.json";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    Failed,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// outcome of a single test in the previous run
+pub struct TestRecord {
+    pub outcome: Outcome,
+    /// how long the test took in miliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+/// struct for holding the outcome of every test of the previous runs, by the path of the
+/// config and the name of the test in it. A run only changes the configs it ran
+pub struct LastRun {
+    #[serde(default)]
+    pub configs: BTreeMap<String, BTreeMap<String, TestRecord>>,
+}
+
+impl LastRun {
+    pub fn path() -> PathBuf {
+        Path::new(STATE_DIR).join(LAST_RUN_FILE)
+    }
+
+    /// load the previous run, returns an empty run if baret has not run before
+    pub fn load() -> io::Result<LastRun> {
+        Self::load_from(&Self::path())
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<LastRun> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(LastRun::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&Self::path())
+    }
+
+    /// writes to a temporary file first, so a concurrent baret never reads half a file
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension(format!("json.{}", std::process::id()));
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn record(&mut self, config: &str, test_name: &str, outcome: Outcome, duration: Duration) {
+        self.configs.entry(config.to_string()).or_default().insert(
+            test_name.to_string(),
+            TestRecord {
+                outcome,
+                duration_ms: Some(duration.as_millis() as u64),
+            },
+        );
+    }
+
+    pub fn duration(&self, config: &str, test_name: &str) -> Option<Duration> {
+        self.configs
+            .get(config)?
+            .get(test_name)
+            .and_then(|record| record.duration_ms)
+            .map(Duration::from_millis)
+    }
+
+    /// the recorded durations of the tests of the suites, by their name in the run
+    pub fn durations(&self, suites: &[Suite]) -> HashMap<String, Duration> {
+        suites
+            .iter()
+            .flat_map(|suite| {
+                let config = suite.config_key();
+                self.configs.get(&config).into_iter().flatten().filter_map(
+                    move |(test_name, record)| {
+                        let duration = Duration::from_millis(record.duration_ms?);
+                        Some((suite.test_name(test_name), duration))
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// the tests of `config` that failed
+    pub fn failed<'a>(&'a self, config: &str) -> impl Iterator<Item = &'a str> {
+        self.configs
+            .get(config)
+            .into_iter()
+            .flatten()
+            .filter(|(_, record)| record.outcome == Outcome::Failed)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// forget about the tests that are not in `config` anymore, the other configs are kept
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, config: &str, mut keep: F) {
+        if let Some(tests) = self.configs.get_mut(config) {
+            tests.retain(|name, _| keep(name));
+            if tests.is_empty() {
+                self.configs.remove(config);
+            }
+        }
+    }
+}
+
+#[test]
+fn last_run_round_trips() {
+    let dir = std::env::temp_dir().join(format!("baret-state-{}", std::process::id()));
+    let path = dir.join(LAST_RUN_FILE);
+
+    let mut last_run = LastRun::default();
+    last_run.record(
+        "a.yaml",
+        "works",
+        Outcome::Passed,
+        Duration::from_millis(20),
+    );
+    last_run.record(
+        "a.yaml",
+        "breaks",
+        Outcome::Failed,
+        Duration::from_millis(10),
+    );
+    last_run.record(
+        "b.yaml",
+        "works",
+        Outcome::Failed,
+        Duration::from_millis(30),
+    );
+    last_run.save_to(&path).unwrap();
+
+    let loaded = LastRun::load_from(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(last_run, loaded);
+    assert_eq!(vec!["breaks"], loaded.failed("a.yaml").collect::<Vec<_>>());
+    assert_eq!(
+        Some(Duration::from_millis(20)),
+        loaded.duration("a.yaml", "works")
+    );
+}
+
+#[test]
+fn retain_keeps_the_other_configs() {
+    let mut last_run = LastRun::default();
+    last_run.record("a.yaml", "gone", Outcome::Failed, Duration::from_millis(10));
+    last_run.record("b.yaml", "gone", Outcome::Failed, Duration::from_millis(10));
+
+    last_run.retain("a.yaml", |_| false);
+
+    assert_eq!(0, last_run.failed("a.yaml").count());
+    assert_eq!(vec!["gone"], last_run.failed("b.yaml").collect::<Vec<_>>());
+}
+
+#[test]
+fn missing_last_run_is_empty() {
+    let path = Path::new("does/not/exist/last-run.json");
+
+    assert_eq!(
+        Ok(LastRun::default()),
+        LastRun::load_from(path).map_err(|_| ())
+    );
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{self, ConfigError, Format, Location};
+use crate::{Context, Data, Error, Tests, TestsOrGroup};
+
+/// the path of a config that is piped in with `-c -`
+pub const STDIN_PATH: &str = "<stdin>";
+
+#[derive(Debug, PartialEq, Clone)]
+/// a config file with its tests, a run can have more than one
+pub struct Suite {
+    /// prefix of the test names, only set when a run has more than one suite
+    pub name: Option<String>,
+    /// the config file, `<stdin>` when it is piped in
+    pub path: PathBuf,
+    pub source: String,
+    pub data: Data,
+    /// directory the commands run in and the group patterns are relative to, the current
+    /// directory when it is not set
+    pub dir: Option<PathBuf>,
+}
+
+impl Suite {
+    /// a suite that is not read from a file
+    pub fn new(data: Data) -> Suite {
+        Suite {
+            name: None,
+            path: PathBuf::new(),
+            source: String::new(),
+            data,
+            dir: None,
+        }
+    }
+
+    pub fn parse<P: Into<PathBuf>>(
+        path: P,
+        source: String,
+        format: Format,
+    ) -> Result<Suite, ConfigError> {
+        let path = path.into();
+        let data = config::parse(&source, &path, format)?;
+
+        Ok(Suite {
+            name: None,
+            path,
+            source,
+            data,
+            dir: None,
+        })
+    }
+
+    /// the file name without extension, `stdin` for a piped config
+    pub fn stem(&self) -> String {
+        if self.path == Path::new(STDIN_PATH) {
+            return String::from("stdin");
+        }
+
+        self.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// the name of a test in the run: `<suite>::<test>`, or just the test without a suite name
+    pub fn test_name(&self, test_name: &str) -> String {
+        match &self.name {
+            Some(name) => format!("{}::{}", name, test_name),
+            None => test_name.to_string(),
+        }
+    }
+
+    /// the name of a test in the config, the opposite of `test_name`
+    pub fn local_name<'a>(&self, test_name: &'a str) -> &'a str {
+        self.name
+            .as_ref()
+            .and_then(|name| test_name.strip_prefix(name.as_str()))
+            .and_then(|test_name| test_name.strip_prefix("::"))
+            .unwrap_or(test_name)
+    }
+
+    /// the tests with their names in the run, group files are read
+    pub fn tests(&self) -> Result<Tests, Error> {
+        let tests = match self.data.test.clone() {
+            TestsOrGroup::Tests(tests) => tests,
+            TestsOrGroup::Group(group) => group.into_tests_in(self.dir())?,
+        };
+
+        Ok(tests
+            .into_iter()
+            .map(|(test_name, test)| (self.test_name(&test_name), test))
+            .collect())
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.as_deref().unwrap_or_else(|| Path::new(""))
+    }
+
+    /// a copy of `ctx` that runs the commands in the directory of the suite
+    pub fn context(&self, ctx: &Context) -> Context {
+        let mut ctx = ctx.clone();
+        if let Some(dir) = &self.dir {
+            ctx = ctx.with_cwd(dir);
+        }
+        if let Some(name) = &self.name {
+            ctx = ctx.with_suite_name(name);
+        }
+        if let Some(config_name) = self.path.file_name() {
+            ctx = ctx.with_config_name(config_name.to_string_lossy());
+        }
+        match self.config_dir() {
+            Some(config_dir) => ctx.with_config_dir(config_dir),
+            None => ctx,
+        }
+    }
+
+    /// the path of the config in the state of the previous runs, without `./`
+    pub fn config_key(&self) -> String {
+        self.path
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .collect::<PathBuf>()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// the directory of the config file, unknown for a piped config or one that is not read
+    /// from a file
+    pub fn config_dir(&self) -> Option<&Path> {
+        if self.path.as_os_str().is_empty() || self.path == Path::new(STDIN_PATH) {
+            return None;
+        }
+
+        self.path.parent()
+    }
+
+    /// where a test is defined, `test_name` is the name in the config. Unknown for a suite
+    /// that is not read from a file
+    pub fn location(&self, test_name: &str) -> Option<Location> {
+        match self.data.test {
+            TestsOrGroup::Group(_) => Some(Location::new(self.dir().join(test_name), None)),
+            TestsOrGroup::Tests(_) if self.path.as_os_str().is_empty() => None,
+            TestsOrGroup::Tests(_) => Some(Location::new(
+                &self.path,
+                config::find_test_line(&self.source, test_name),
+            )),
+        }
+    }
+}
+
+/// names the suites without a name after their file when there is more than one, so their
+/// tests do not clash. Two suites with the same name are an error
+pub fn name_suites(suites: &mut [Suite]) -> Result<(), String> {
+    if suites.len() < 2 {
+        return Ok(());
+    }
+
+    let mut names = HashSet::new();
+    for suite in suites.iter_mut() {
+        let name = suite.name.clone().unwrap_or_else(|| suite.stem());
+        if !names.insert(name.clone()) {
+            return Err(format!(
+                "more than one config is named '{}', the test names would clash",
+                name
+            ));
+        }
+        suite.name = Some(name);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn suite_names_prefix_the_tests() {
+    let source = String::from("test:\n  works:\n    test: exit 0\n");
+    let mut suites = vec![
+        Suite::parse("tests/api.yaml", source.clone(), Format::Yaml).unwrap(),
+        Suite::parse(STDIN_PATH, source.clone(), Format::Yaml).unwrap(),
+    ];
+
+    name_suites(&mut suites).unwrap();
+
+    let tests = suites[0].tests().unwrap();
+    assert_eq!(vec!["api::works"], tests.keys().collect::<Vec<_>>());
+    assert_eq!("works", suites[0].local_name("api::works"));
+    assert_eq!(Some(String::from("stdin")), suites[1].name);
+
+    let mut suites = vec![suites[0].clone(), suites[0].clone()];
+    assert!(name_suites(&mut suites).is_err());
+}
@@ -1,15 +1,24 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::collections::HashMap;
 use std::fs::read_to_string;
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::settings::{GlobalSettings, Settings, SettingsStack};
-use crate::{command, Error};
+use crate::assertion::FileAssertions;
+use crate::event::RunEvent;
+use crate::interactive::{self, Step};
+use crate::output::CommandOutput;
+use crate::settings::{GlobalSettings, Settings, SettingsBuilder, SettingsStack};
+use crate::snapshot::{self, Snapshot};
+use crate::timing::{Phase, Timings};
+use crate::{command, Context, Error};
 
 pub type Tests = HashMap<String, Test>;
 
-#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
 /// struct for holding the actual test case
 pub struct Test {
     /// script to run before the test
@@ -18,68 +27,214 @@ pub struct Test {
     pub after: Option<String>,
     /// the actual test script
     pub test: String,
+    /// run `test` in a terminal and go through these steps, to test prompts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+    /// text for the stdin of `test`, without it or `stdin_file` stdin is empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdin: Option<String>,
+    /// file for the stdin of `test`, relative to the directory the test runs in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdin_file: Option<PathBuf>,
+    /// compare the output of `test` to a stored snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Snapshot>,
+    #[serde(default, flatten)]
+    pub files: FileAssertions,
 
     #[serde(default, flatten)]
     pub settings: Settings,
 }
 
 impl Test {
+    /// the keys in a config file next to the `Settings::FIELDS`
+    pub const FIELDS: &[&str] = &[
+        "before",
+        "after",
+        "test",
+        "steps",
+        "stdin",
+        "stdin_file",
+        "snapshot",
+    ];
+
+    pub fn builder() -> TestBuilder {
+        TestBuilder::default()
+    }
+
     pub fn dump_example() -> Test {
         Test {
             before: Some(String::new()),
             after: Some(String::new()),
             test: String::from("echo 'test'"),
+            steps: Vec::new(),
+            stdin: None,
+            stdin_file: None,
+            snapshot: None,
+            files: FileAssertions::default(),
             settings: Settings::default().return_defaults(),
         }
     }
 
     pub async fn run(&self, global: &GlobalSettings) -> Result<(), Error> {
-        let stack = &[&self.settings];
-        let settings = global.stack(stack);
-
-        self.run_before(&settings).await?;
-        let test_result = self.run_test(&settings).await;
-        self.run_after(&settings).await?;
+        self.run_with_context(global, &Context::default())
+            .await
+            .result
+    }
 
-        test_result?;
-        Ok(())
+    /// when `ctx` gets cancelled the running command is killed, `after` will still run
+    pub async fn run_arc_settings(self, global: Arc<GlobalSettings>, ctx: Context) -> TestReport {
+        self.run_with_context(&global, &ctx).await
     }
 
-    pub async fn run_arc_settings(self, global: Arc<GlobalSettings>) -> Result<(), Error> {
+    /// runs `before` and `test`, `after` always runs. The errors are tagged with their phase
+    pub async fn run_with_context(&self, global: &GlobalSettings, ctx: &Context) -> TestReport {
         let stack = &[&self.settings];
         let settings = global.stack(stack);
+        let mut timings = Timings::default();
+        let mut errors = Vec::new();
+        let mut output = None;
+        if let Some(test_name) = ctx.test_name() {
+            ctx.emit(RunEvent::TestStarted {
+                test_name: test_name.to_string(),
+            });
+        }
 
-        self.run_before(&settings).await?;
-        self.run_test(&settings).await?;
-        self.run_after(&settings).await?;
+        for phase in [Phase::Before, Phase::Test].iter() {
+            match self.run_phase(*phase, &settings, ctx, &mut timings).await {
+                Ok(Some(phase_output)) if *phase == Phase::Test => {
+                    let snapshot = match &self.snapshot {
+                        Some(snapshot) => {
+                            snapshot::check(snapshot, &phase_output, &settings, ctx).await
+                        }
+                        None => Ok(()),
+                    };
+                    if let Err(error) = snapshot {
+                        errors.push(error.in_phase(*phase));
+                    }
+                    for error in self.files.run(&settings, ctx).await {
+                        errors.push(error.in_phase(*phase));
+                    }
+                    output = Some(phase_output);
+                }
+                Ok(_) => (),
+                Err(error) => {
+                    errors.push(error.in_phase(*phase));
+                    break;
+                }
+            }
+        }
 
-        Ok(())
-    }
+        let cancelled = errors.iter().any(Error::is_cancelled);
+        let after_ctx = if cancelled {
+            ctx.uncancellable()
+        } else {
+            ctx.clone()
+        };
+        if let Err(error) = self
+            .run_phase(Phase::After, &settings, &after_ctx, &mut timings)
+            .await
+        {
+            errors.push(error.in_phase(Phase::After));
+        }
 
-    async fn run_before<'a, 'b>(&'a self, settings: &SettingsStack<'a, 'b>) -> Result<(), Error> {
-        if let Some(ref before) = self.before {
-            command::run(before, settings).await?;
+        let logs = ctx.services().logs();
+        let result = if cancelled {
+            Err(Error::Cancelled)
+        } else {
+            Error::from_many(errors)
+                .map_err(|error| error.with_context(|context| context.services = logs))
+        };
+        TestReport {
+            result,
+            timings,
+            output,
         }
+    }
 
-        Ok(())
+    /// the snapshot files of the test, relative to the directory of the config
+    pub fn snapshots(&self, test_name: &str) -> Vec<PathBuf> {
+        let mut snapshots: Vec<PathBuf> = self
+            .snapshot
+            .iter()
+            .filter_map(|snapshot| snapshot.path(test_name))
+            .collect();
+        snapshots.extend(
+            self.files
+                .file_matches_snapshot
+                .iter()
+                .map(|file| snapshot::file_path(test_name, file)),
+        );
+        snapshots
+    }
+
+    fn script(&self, phase: Phase) -> Option<&str> {
+        match phase {
+            Phase::Before => self.before.as_deref(),
+            Phase::Test => Some(&self.test),
+            Phase::After => self.after.as_deref(),
+            Phase::BeforeAll | Phase::AfterAll => None,
+        }
     }
 
-    async fn run_after<'a, 'b>(&'a self, settings: &SettingsStack<'a, 'b>) -> Result<(), Error> {
-        if let Some(ref after) = self.after {
-            command::run(after, settings).await?;
+    async fn run_phase<'a, 'b>(
+        &'a self,
+        phase: Phase,
+        settings: &SettingsStack<'a, 'b>,
+        ctx: &Context,
+        timings: &mut Timings,
+    ) -> Result<Option<CommandOutput>, Error> {
+        if let Some(script) = self.script(phase) {
+            let ctx = ctx.for_phase(phase);
+            ctx.phase_started();
+            let start = Instant::now();
+            let result = if phase != Phase::Test {
+                command::run_with_output(script, settings, &ctx).await
+            } else if !self.steps.is_empty() {
+                interactive::run(script, &self.steps, settings, &ctx).await
+            } else {
+                match self.stdin(&ctx).await {
+                    Ok(stdin) => command::run_with_input(script, stdin, settings, &ctx).await,
+                    Err(error) => Err(error),
+                }
+            };
+            timings.add(phase, start.elapsed());
+            return result.map(Some);
         }
 
-        Ok(())
+        Ok(None)
     }
 
-    async fn run_test<'a, 'b>(&'a self, settings: &SettingsStack<'a, 'b>) -> Result<(), Error> {
-        command::run(&self.test, settings).await?;
-        Ok(())
+    async fn stdin(&self, ctx: &Context) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(stdin) = &self.stdin {
+            return Ok(Some(stdin.clone().into_bytes()));
+        }
+        if let Some(stdin_file) = &self.stdin_file {
+            let path = match ctx.cwd() {
+                Some(cwd) => cwd.join(stdin_file),
+                None => stdin_file.clone(),
+            };
+            return match tokio::fs::read(&path).await {
+                Ok(stdin) => Ok(Some(stdin)),
+                Err(e) => Err(Error::from(e).with_context(|context| context.path = Some(path))),
+            };
+        }
+
+        Ok(None)
     }
 }
 
+#[derive(Debug)]
+/// the result of a test together with the time spent in each phase
+pub struct TestReport {
+    pub result: Result<(), Error>,
+    pub timings: Timings,
+    /// output of the `test` script when the test passed, when it failed the output is in the error
+    pub output: Option<CommandOutput>,
+}
+
 #[serde_as]
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 /// struct for holding the actual test case
 pub struct Group {
     /// script to run before the test
@@ -88,12 +243,22 @@ pub struct Group {
     pub after: Option<String>,
     /// the test script file regexes
     #[serde_as(as = "serde_with::OneOrMany<serde_with::DisplayFromStr>")]
+    #[schemars(with = "OneOrMany")]
     pub files: Vec<glob::Pattern>,
 
     #[serde(default, flatten)]
     pub settings: Settings,
 }
 
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[serde(untagged)]
+/// schema of `files`, a single pattern or a list of them
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
 impl PartialEq for Group {
     fn eq(&self, other: &Self) -> bool {
         (self.before == other.before)
@@ -109,18 +274,46 @@ impl PartialEq for Group {
 }
 
 impl Group {
+    /// the keys in a config file next to the `Settings::FIELDS`
+    pub const FIELDS: &[&str] = &["before", "after", "files"];
+
+    pub fn builder() -> GroupBuilder {
+        GroupBuilder::default()
+    }
+
     pub fn into_tests(self) -> Result<Tests, Error> {
+        self.into_tests_in(Path::new(""))
+    }
+
+    /// the patterns are relative to `dir`, so are the names of the tests
+    pub fn into_tests_in(self, dir: &Path) -> Result<Tests, Error> {
         let mut hashmap = HashMap::new();
-        for item in self.files()? {
+        // glob leaves out the `./` parts of the pattern
+        let prefix: PathBuf = dir
+            .components()
+            .filter(|component| component != &Component::CurDir)
+            .collect();
+        for item in self.files_in(dir)? {
             let path = item?;
+            let test = read_to_string(&path).map_err(|e| {
+                Error::from(e).with_context(|context| context.path = Some(path.clone()))
+            })?;
 
             hashmap.insert(
-                path.to_string_lossy().to_string(),
+                path.strip_prefix(&prefix)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .to_string(),
                 Test {
-                    after: self.before.clone(),
+                    after: self.after.clone(),
                     before: self.before.clone(),
                     settings: self.settings.clone(),
-                    test: read_to_string(&path)?,
+                    test,
+                    steps: Vec::new(),
+                    stdin: None,
+                    stdin_file: None,
+                    snapshot: None,
+                    files: FileAssertions::default(),
                 },
             );
         }
@@ -129,12 +322,238 @@ impl Group {
     }
 
     pub fn files(&self) -> Result<Box<dyn Iterator<Item = glob::GlobResult>>, Error> {
-        let mut iterator: Box<dyn Iterator<Item = _>> = Box::new(std::iter::Empty::default());
+        self.files_in(Path::new(""))
+    }
+
+    /// the files matching the patterns relative to `dir`
+    pub fn files_in(
+        &self,
+        dir: &Path,
+    ) -> Result<Box<dyn Iterator<Item = glob::GlobResult>>, Error> {
+        let mut iterator: Box<dyn Iterator<Item = _>> = Box::new(std::iter::empty());
         for file in self.files.clone() {
-            let paths = glob::glob(file.as_str())?;
+            let pattern = dir.join(file.as_str());
+            let paths = glob::glob(&pattern.to_string_lossy()).map_err(|e| {
+                Error::from(e).with_context(|context| context.path = Some(file.as_str().into()))
+            })?;
             iterator = Box::new(iterator.chain(paths));
         }
 
         Ok(iterator)
     }
 }
+
+#[derive(Debug, Default)]
+/// builds a `Test` in code, see `Settings::builder` for all the settings
+pub struct TestBuilder {
+    test: Test,
+    settings: SettingsBuilder,
+}
+
+impl TestBuilder {
+    /// the actual test script
+    pub fn script<S: Into<String>>(mut self, script: S) -> TestBuilder {
+        self.test.test = script.into();
+        self
+    }
+
+    pub fn before<S: Into<String>>(mut self, before: S) -> TestBuilder {
+        self.test.before = Some(before.into());
+        self
+    }
+
+    pub fn after<S: Into<String>>(mut self, after: S) -> TestBuilder {
+        self.test.after = Some(after.into());
+        self
+    }
+
+    /// adds a step, the test runs in a terminal when it has steps
+    pub fn step(mut self, step: Step) -> TestBuilder {
+        self.test.steps.push(step);
+        self
+    }
+
+    /// text for the stdin of the test script
+    pub fn stdin<S: Into<String>>(mut self, stdin: S) -> TestBuilder {
+        self.test.stdin = Some(stdin.into());
+        self
+    }
+
+    /// file for the stdin of the test script
+    pub fn stdin_file<P: Into<PathBuf>>(mut self, stdin_file: P) -> TestBuilder {
+        self.test.stdin_file = Some(stdin_file.into());
+        self
+    }
+
+    pub fn snapshot(mut self, snapshot: Snapshot) -> TestBuilder {
+        self.test.snapshot = Some(snapshot);
+        self
+    }
+
+    /// a file that has to exist after the test
+    pub fn file_exists<P: Into<PathBuf>>(mut self, path: P) -> TestBuilder {
+        self.test.files.files_exist.push(path.into());
+        self
+    }
+
+    /// a file that may not exist after the test
+    pub fn file_absent<P: Into<PathBuf>>(mut self, path: P) -> TestBuilder {
+        self.test.files.files_absent.push(path.into());
+        self
+    }
+
+    /// a file with a regex that has to match its content after the test
+    pub fn file_contains<P: Into<PathBuf>, S: Into<String>>(
+        mut self,
+        path: P,
+        pattern: S,
+    ) -> TestBuilder {
+        self.test
+            .files
+            .file_contains
+            .insert(path.into(), pattern.into());
+        self
+    }
+
+    pub fn file_matches_snapshot<P: Into<PathBuf>>(mut self, path: P) -> TestBuilder {
+        self.test.files.file_matches_snapshot.push(path.into());
+        self
+    }
+
+    /// a directory that has to be equal to the fixture directory after the test
+    pub fn dir_matches<P: Into<PathBuf>, F: Into<PathBuf>>(
+        mut self,
+        path: P,
+        fixture: F,
+    ) -> TestBuilder {
+        self.test
+            .files
+            .dir_matches
+            .insert(path.into(), fixture.into());
+        self
+    }
+
+    /// replaces the settings set so far
+    pub fn settings(mut self, settings: Settings) -> TestBuilder {
+        self.settings = SettingsBuilder::from(settings);
+        self
+    }
+
+    /// in miliseconds
+    pub fn timeout(mut self, timeout: u32) -> TestBuilder {
+        self.settings = self.settings.timeout(timeout);
+        self
+    }
+
+    pub fn command<S: Into<String>>(mut self, command: S) -> TestBuilder {
+        self.settings = self.settings.command(command);
+        self
+    }
+
+    pub fn should_fail(mut self, should_fail: bool) -> TestBuilder {
+        self.settings = self.settings.should_fail(should_fail);
+        self
+    }
+
+    /// adds an environment variable, can be called more than once
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> TestBuilder {
+        self.settings = self.settings.env(key, value);
+        self
+    }
+
+    pub fn build(self) -> Test {
+        Test {
+            settings: self.settings.build(),
+            ..self.test
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+/// builds a `Group` in code, see `Settings::builder` for all the settings
+pub struct GroupBuilder {
+    group: Group,
+    settings: SettingsBuilder,
+}
+
+impl GroupBuilder {
+    /// adds a pattern of test files, can be called more than once
+    pub fn files(mut self, pattern: glob::Pattern) -> GroupBuilder {
+        self.group.files.push(pattern);
+        self
+    }
+
+    pub fn before<S: Into<String>>(mut self, before: S) -> GroupBuilder {
+        self.group.before = Some(before.into());
+        self
+    }
+
+    pub fn after<S: Into<String>>(mut self, after: S) -> GroupBuilder {
+        self.group.after = Some(after.into());
+        self
+    }
+
+    /// replaces the settings set so far
+    pub fn settings(mut self, settings: Settings) -> GroupBuilder {
+        self.settings = SettingsBuilder::from(settings);
+        self
+    }
+
+    /// in miliseconds
+    pub fn timeout(mut self, timeout: u32) -> GroupBuilder {
+        self.settings = self.settings.timeout(timeout);
+        self
+    }
+
+    pub fn command<S: Into<String>>(mut self, command: S) -> GroupBuilder {
+        self.settings = self.settings.command(command);
+        self
+    }
+
+    pub fn should_fail(mut self, should_fail: bool) -> GroupBuilder {
+        self.settings = self.settings.should_fail(should_fail);
+        self
+    }
+
+    /// adds an environment variable, can be called more than once
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> GroupBuilder {
+        self.settings = self.settings.env(key, value);
+        self
+    }
+
+    pub fn build(self) -> Group {
+        Group {
+            settings: self.settings.build(),
+            ..self.group
+        }
+    }
+}
+
+#[test]
+fn built_test_round_trips_through_yaml() {
+    let test = Test::builder()
+        .script("echo $NAME")
+        .after("true")
+        .timeout(100)
+        .env("NAME", "baret")
+        .build();
+    let data = crate::Data {
+        test: crate::TestsOrGroup::Tests(vec![(String::from("echo"), test)].into_iter().collect()),
+        ..crate::Data::default()
+    };
+
+    let yaml = serde_yaml::to_string(&data).unwrap();
+    let parsed =
+        crate::config::parse(&yaml, Path::new("built.yaml"), crate::config::Format::Yaml).unwrap();
+    assert_eq!(data, parsed);
+
+    let group = Group::builder()
+        .files(glob::Pattern::new("tests/*.sh").unwrap())
+        .command("bash -c")
+        .build();
+    assert_eq!("bash -c", group.settings.command());
+    assert_eq!(
+        vec!["tests/*.sh"],
+        group.files.iter().map(|p| p.as_str()).collect::<Vec<_>>()
+    );
+}
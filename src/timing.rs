@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// the parts of a test, and the setup around all the tests
+pub enum Phase {
+    BeforeAll,
+    Before,
+    Test,
+    After,
+    AfterAll,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::BeforeAll => write!(f, "before_all"),
+            Phase::Before => write!(f, "before"),
+            Phase::Test => write!(f, "test"),
+            Phase::After => write!(f, "after"),
+            Phase::AfterAll => write!(f, "after_all"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+/// time spent in every phase of a test
+pub struct Timings {
+    pub before: Duration,
+    pub test: Duration,
+    pub after: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.before + self.test + self.after
+    }
+
+    pub fn add(&mut self, phase: Phase, duration: Duration) {
+        match phase {
+            Phase::Before => self.before += duration,
+            Phase::Test => self.test += duration,
+            Phase::After => self.after += duration,
+            Phase::BeforeAll | Phase::AfterAll => (),
+        }
+    }
+}
+
+/// phases that did not run are left out: `1.03s (before 10.00ms, test 1.00s)`
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.2?} (", self.total())?;
+        let phases = [
+            (Phase::Before, self.before),
+            (Phase::Test, self.test),
+            (Phase::After, self.after),
+        ];
+        let mut first = true;
+        for (phase, duration) in phases.iter() {
+            if duration.is_zero() && !(*phase == Phase::Test && self.total().is_zero()) {
+                continue;
+            }
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {:.2?}", phase, duration)?;
+            first = false;
+        }
+        write!(f, ")")
+    }
+}
+
+#[test]
+fn timings_add_up() {
+    let mut timings = Timings::default();
+    timings.add(Phase::Before, Duration::from_millis(10));
+    timings.add(Phase::Test, Duration::from_millis(1000));
+    timings.add(Phase::After, Duration::from_millis(20));
+
+    assert_eq!(Duration::from_millis(1030), timings.total());
+    assert_eq!(
+        "1.03s (before 10.00ms, test 1.00s, after 20.00ms)",
+        timings.to_string()
+    );
+}
+
+#[test]
+fn timings_leave_out_phases_that_did_not_run() {
+    let mut timings = Timings::default();
+    timings.add(Phase::Test, Duration::from_millis(20));
+
+    assert_eq!("20.00ms (test 20.00ms)", timings.to_string());
+}
@@ -0,0 +1,407 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::config::{find_key, ConfigError, Diagnostic};
+use crate::executor::container_runtime;
+use crate::output::ShowOutput;
+use crate::service::Service;
+use crate::settings::{Settings, SettingsStack};
+use crate::snapshot::{self, Snapshot};
+use crate::suite::Suite;
+use crate::{Test, TestsOrGroup};
+
+/// checks the config without running anything: group patterns match files, the programs of
+/// the commands exist, timeouts are not 0 and env names are valid. Every problem is reported
+pub fn validate(suite: &Suite) -> Result<(), ConfigError> {
+    let data = &suite.data;
+    let mut validator = Validator {
+        source: &suite.source,
+        dir: suite.dir(),
+        diagnostics: Vec::new(),
+    };
+
+    validator.settings(&data.global, &["global"]);
+    validator.command(&data.global.stack(&[]), &["global"]);
+
+    for (name, service) in &data.services {
+        validator.service(service, &["services", name.as_str()]);
+    }
+
+    match &data.test {
+        TestsOrGroup::Tests(tests) => {
+            for (test_name, test) in tests {
+                let path = ["test", test_name.as_str()];
+                validator.settings(&test.settings, &path);
+                validator.command(&data.global.stack(&[&test.settings]), &path);
+                validator.steps(test, &data.global.stack(&[&test.settings]), &path);
+                validator.stdin(test, &path);
+                validator.snapshot(test, &data.global.stack(&[&test.settings]), &path);
+                validator.files(test, &data.global.stack(&[&test.settings]), &path);
+            }
+        }
+        TestsOrGroup::Group(group) => {
+            validator.settings(&group.settings, &["test"]);
+            validator.command(&data.global.stack(&[&group.settings]), &["test"]);
+            for pattern in &group.files {
+                let pattern_in_dir = suite.dir().join(pattern.as_str());
+                let matches = glob::glob(&pattern_in_dir.to_string_lossy())
+                    .map(|mut paths| paths.any(|path| path.is_ok()))
+                    .unwrap_or(false);
+                if !matches {
+                    validator.push(
+                        format!("pattern `{}` does not match any file", pattern),
+                        &["test", "files"],
+                        None,
+                    );
+                }
+            }
+        }
+    }
+
+    if let (Some(config_dir), Some(config_name)) = (suite.config_dir(), suite.path.file_name()) {
+        let snapshots = match &data.test {
+            TestsOrGroup::Tests(tests) => tests
+                .iter()
+                .flat_map(|(test_name, test)| test.snapshots(test_name))
+                .collect(),
+            TestsOrGroup::Group(_) => Vec::new(),
+        };
+        for orphan in snapshot::orphans(config_dir, &config_name.to_string_lossy(), snapshots) {
+            validator.push(
+                format!(
+                    "snapshot {} does not belong to a test, remove it when the test is gone",
+                    orphan.display()
+                ),
+                &[],
+                None,
+            );
+        }
+    }
+
+    let mut diagnostics = validator.diagnostics;
+    if diagnostics.is_empty() {
+        return Ok(());
+    }
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics.dedup();
+    Err(ConfigError::new(&suite.path, &suite.source, diagnostics))
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    dir: &'a Path,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    /// reports the problem at the key in `path`, or at its parent when the key is not there
+    fn push(&mut self, message: String, path: &[&str], help: Option<String>) {
+        let (location, key) = (1..=path.len())
+            .rev()
+            .find_map(|length| {
+                let location = find_key(self.source, &path[..length])?;
+                Some((Some(location), path[length - 1]))
+            })
+            .unwrap_or((None, ""));
+
+        self.diagnostics.push(Diagnostic {
+            message,
+            line: location.map(|(line, _)| line),
+            column: location.map(|(_, column)| column),
+            width: key.chars().count(),
+            help,
+        });
+    }
+
+    /// checks the settings in a single layer, only what is set in that layer
+    fn settings(&mut self, settings: &Settings, path: &[&str]) {
+        for (key, timeout) in [
+            ("timeout", settings.timeout()),
+            ("setup_timeout", settings.setup_timeout()),
+            ("output_limit", settings.output_limit()),
+        ] {
+            if timeout == 0 {
+                self.push(
+                    format!("`{}` must be more than 0", key),
+                    &[path, &[key]].concat(),
+                    None,
+                );
+            }
+        }
+
+        for (index, filter) in settings.snapshot_filters().iter().enumerate() {
+            if let Err(e) = filter.check() {
+                self.push(
+                    format!("snapshot filter {}: {}", index + 1, e),
+                    &[path, &["snapshot_filters"]].concat(),
+                    None,
+                );
+            }
+        }
+
+        if settings.image().is_some() && !settings.executor().is_local() {
+            self.push(
+                String::from("`image` can not be used together with `executor`"),
+                &[path, &["image"]].concat(),
+                None,
+            );
+        }
+
+        let mut names: Vec<&String> = settings.env().keys().collect();
+        names.sort();
+        for name in names {
+            if !is_identifier(name) {
+                self.push(
+                    format!("`{}` is not a valid environment variable name", name),
+                    &[path, &["env", name.as_str()]].concat(),
+                    Some(String::from(
+                        "use only letters, digits and `_`, not starting with a digit",
+                    )),
+                );
+            }
+        }
+    }
+
+    fn command(&mut self, settings: &SettingsStack<'_, '_>, path: &[&str]) {
+        if settings.image().is_some() {
+            if container_runtime().is_none() {
+                self.push(
+                    String::from("`image` needs docker or podman, neither is found in PATH"),
+                    &[path, &["image"]].concat(),
+                    None,
+                );
+            }
+            return;
+        }
+        // the program is looked up on the machine or in the container that runs it
+        if !settings.executor().is_local() {
+            return;
+        }
+        let (program, _) = settings.command_with_args();
+        // the layers come after the root, the last `PATH` is the one the command gets
+        let env_path = settings
+            .env()
+            .filter(|(key, _)| key.as_str() == "PATH")
+            .last()
+            .map(|(_, value)| OsString::from(value))
+            .or_else(|| std::env::var_os("PATH"));
+
+        if find_program(&program, env_path, self.dir).is_none() {
+            // the command can come from the test, or from the global settings
+            let command_path = [path, &["command"]].concat();
+            let path = if find_key(self.source, &command_path).is_some() {
+                command_path
+            } else {
+                vec!["global", "command"]
+            };
+            self.push(
+                format!("program `{}` not found in PATH", program),
+                &path,
+                None,
+            );
+        }
+    }
+
+    fn steps(&mut self, test: &Test, settings: &SettingsStack<'_, '_>, path: &[&str]) {
+        if test.steps.is_empty() {
+            return;
+        }
+        let steps_path = [path, &["steps"]].concat();
+        if settings.image().is_some() || !settings.executor().is_local() {
+            self.push(
+                String::from(
+                    "`steps` run on this machine, they can not be used with `image` or `executor`",
+                ),
+                &steps_path,
+                None,
+            );
+        }
+        for (index, step) in test.steps.iter().enumerate() {
+            if let Err(e) = step.check() {
+                self.push(format!("step {}: {}", index + 1, e), &steps_path, None);
+            }
+        }
+    }
+
+    fn snapshot(&mut self, test: &Test, settings: &SettingsStack<'_, '_>, path: &[&str]) {
+        let enabled = test.snapshot.as_ref().is_some_and(Snapshot::is_enabled);
+        if enabled && settings.show_output() == ShowOutput::Never {
+            self.push(
+                String::from(
+                    "`snapshot` needs the output, it can not be used with `show_output: never`",
+                ),
+                &[path, &["snapshot"]].concat(),
+                None,
+            );
+        }
+    }
+
+    fn files(&mut self, test: &Test, settings: &SettingsStack<'_, '_>, path: &[&str]) {
+        if test.files.is_empty() {
+            return;
+        }
+        if !settings.executor().is_local() {
+            self.push(
+                String::from(
+                    "the files are checked on this machine, they can not be checked with `executor`",
+                ),
+                &[path, &["executor"]].concat(),
+                None,
+            );
+        }
+        if let Err(e) = test.files.check() {
+            self.push(e, &[path, &["file_contains"]].concat(), None);
+        }
+        for fixture in test.files.dir_matches.values() {
+            if !self.dir.join(fixture).is_dir() {
+                self.push(
+                    format!("fixture {} is not a directory", fixture.display()),
+                    &[path, &["dir_matches"]].concat(),
+                    None,
+                );
+            }
+        }
+    }
+
+    fn service(&mut self, service: &Service, path: &[&str]) {
+        if let Some(Err(e)) = service.ready.as_ref().map(|probe| probe.check()) {
+            self.push(e, &[path, &["ready"]].concat(), None);
+        }
+        if service.timeout == Some(0) {
+            self.push(
+                String::from("`timeout` must be more than 0"),
+                &[path, &["timeout"]].concat(),
+                None,
+            );
+        }
+        if let Some(cwd) = &service.cwd {
+            if !self.dir.join(cwd).is_dir() {
+                self.push(
+                    format!("`cwd` {} is not a directory", cwd.display()),
+                    &[path, &["cwd"]].concat(),
+                    None,
+                );
+            }
+        }
+    }
+
+    fn stdin(&mut self, test: &Test, path: &[&str]) {
+        let stdin_path = [path, &["stdin"]].concat();
+        let stdin_file_path = [path, &["stdin_file"]].concat();
+        if test.stdin.is_some() && test.stdin_file.is_some() {
+            self.push(
+                String::from("`stdin` can not be used together with `stdin_file`"),
+                &stdin_file_path,
+                None,
+            );
+        }
+        if (test.stdin.is_some() || test.stdin_file.is_some()) && !test.steps.is_empty() {
+            self.push(
+                String::from(
+                    "`steps` send the input themselves, `stdin` can not be used with them",
+                ),
+                if test.stdin.is_some() {
+                    &stdin_path
+                } else {
+                    &stdin_file_path
+                },
+                None,
+            );
+        }
+        if let Some(stdin_file) = &test.stdin_file {
+            if !self.dir.join(stdin_file).is_file() {
+                self.push(
+                    format!("`stdin_file` {} does not exist", stdin_file.display()),
+                    &stdin_file_path,
+                    None,
+                );
+            }
+        }
+    }
+}
+
+/// the path of `program` like the shell would find it
+fn find_program(program: &str, env_path: Option<OsString>, dir: &Path) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = dir.join(program);
+        return if path.is_file() { Some(path) } else { None };
+    }
+
+    std::env::split_paths(&env_path?)
+        .map(|directory| directory.join(program))
+        .find(|path| path.is_file())
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[test]
+fn validate_reports_everything() {
+    let source = "test:\n  broken:\n    test: exit 0\n    timeout: 0\n    output_limit: 0\n    command: not-a-real-program -c\n    env:\n      MY-VAR: x\n      GOOD_VAR: y\n";
+    let suite = Suite::parse(
+        "broken.yaml",
+        source.to_string(),
+        crate::config::Format::Yaml,
+    )
+    .unwrap();
+
+    let error = validate(&suite).unwrap_err();
+    let messages: Vec<_> = error
+        .diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+        .collect();
+
+    assert_eq!(
+        vec![
+            (Some(4), "`timeout` must be more than 0"),
+            (Some(5), "`output_limit` must be more than 0"),
+            (Some(6), "program `not-a-real-program` not found in PATH"),
+            (Some(8), "`MY-VAR` is not a valid environment variable name"),
+        ],
+        messages
+    );
+}
+
+#[test]
+fn validate_group_pattern_without_files() {
+    let source = "test:\n  files: tests/test_data/does-not-exist/*.sh\n";
+    let suite = Suite::parse(
+        "group.yaml",
+        source.to_string(),
+        crate::config::Format::Yaml,
+    )
+    .unwrap();
+
+    let error = validate(&suite).unwrap_err();
+
+    assert_eq!(1, error.diagnostics.len());
+    assert_eq!(
+        "pattern `tests/test_data/does-not-exist/*.sh` does not match any file",
+        error.diagnostics[0].message
+    );
+    assert_eq!(Some(2), error.diagnostics[0].line);
+}
+
+#[test]
+fn validate_uses_the_path_of_the_test() {
+    let source = "test:\n  own path:\n    test: exit 0\n    command: sh -c\n    env:\n      PATH: /does-not-exist\nglobal:\n  env:\n    PATH: /bin:/usr/bin\n";
+    let suite = Suite::parse("path.yaml", source.to_string(), crate::config::Format::Yaml).unwrap();
+
+    let error = validate(&suite).unwrap_err();
+
+    assert_eq!(1, error.diagnostics.len());
+    assert_eq!(
+        "program `sh` not found in PATH",
+        error.diagnostics[0].message
+    );
+    assert_eq!(Some(4), error.diagnostics[0].line);
+}
@@ -88,6 +88,7 @@ fn run_error() -> Result<(), Box<dyn std::error::Error>> {
     assertion
         .stderr(predicate::str::contains(
             r#"Failed test: 'breaks'
+phase: test
 exit code: 1
 stdout:
 im now going to break :'(
@@ -97,6 +98,7 @@ stderr:
         ))
         .stderr(predicate::str::contains(
             r#"Failed test: 'another'
+phase: test
 exit code: 1
 stdout:
 im now going to again :D
@@ -199,6 +201,29 @@ fn verify_should_fail() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn run_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/timeout.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed test: 'too slow'"))
+        .stderr(predicate::str::contains(
+            "the command did not exit within 200 ms",
+        ))
+        .stderr(predicate::str::contains("fast enough").not());
+
+    Ok(())
+}
+
+#[test]
+fn verify_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/timeout.yaml")?;
+
+    Ok(())
+}
+
 #[test]
 fn run_groups() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
@@ -235,11 +260,18 @@ fn verify_groups_many() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 #[ignore]
-fn meta_failure() -> () {
+fn meta_failure() {
     // test that fails, but is used for the meta test
     panic!()
 }
 
+/// a port nothing listens on right now
+fn free_port() -> Result<u16, Box<dyn std::error::Error>> {
+    Ok(std::net::TcpListener::bind(("127.0.0.1", 0))?
+        .local_addr()?
+        .port())
+}
+
 fn verify(file: &str) -> Result<Command, Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
 
@@ -248,3 +280,1024 @@ fn verify(file: &str) -> Result<Command, Box<dyn std::error::Error>> {
 
     Ok(cmd)
 }
+
+#[test]
+fn run_failed_only_reruns_failures() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let config = std::env::current_dir()?.join("tests/test_data/last_failed.yaml");
+
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.current_dir(workdir.path()).arg("-c").arg(&config);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed test: 'flaky'"));
+
+    assert!(workdir.path().join(".baret/last-run.json").exists());
+
+    // `stable` would fail now, so this only passes if just `flaky` is run
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.current_dir(workdir.path())
+        .env("BARET_FIXED", "1")
+        .arg("--failed")
+        .arg("-c")
+        .arg(&config);
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn run_failed_keeps_the_failures_of_other_configs() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let config = std::env::current_dir()?.join("tests/test_data/last_failed.yaml");
+    let other = std::env::current_dir()?.join("tests/test_data/simple.yaml");
+
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.current_dir(workdir.path()).arg("-c").arg(&config);
+    cmd.assert().failure();
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.current_dir(workdir.path()).arg("-c").arg(&other);
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.current_dir(workdir.path())
+        .env("BARET_FIXED", "1")
+        .arg("--failed")
+        .arg("-c")
+        .arg(&config);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("warning").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_failed_without_failures() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let config = std::env::current_dir()?.join("tests/test_data/simple.yaml");
+
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.current_dir(workdir.path())
+        .arg("--failed")
+        .arg("-c")
+        .arg(&config);
+    cmd.assert().success().stderr(predicate::str::contains(
+        "warning: no test failed in the previous run, there is nothing to run",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_last_failed() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/last_failed.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_fail_fast() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let marker = workdir.path().join("after_ran");
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--fail-fast")
+        .arg("-c")
+        .arg("tests/test_data/fail_fast.yaml")
+        .env("BARET_AFTER_MARKER", &marker)
+        .timeout(std::time::Duration::from_secs(20));
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed test: 'a breaks'"))
+        .stderr(predicate::str::contains("Failed test: 'b sleeps'").not())
+        .stderr(predicate::str::contains(
+            "Stopped after 1 failed test, 2 tests not run",
+        ));
+
+    assert!(marker.exists(), "`after` of the cancelled test did not run");
+
+    Ok(())
+}
+
+#[test]
+fn verify_fail_fast() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/fail_fast.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn max_failures_zero_is_rejected() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--max-failures")
+        .arg("0")
+        .arg("-c")
+        .arg("tests/test_data/simple.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--max-failures"));
+
+    Ok(())
+}
+
+#[test]
+fn list_shows_shards() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    let output = cmd
+        .arg("--list")
+        .arg("--shard")
+        .arg("1/3")
+        .arg("-c")
+        .arg("tests/test_data/more.yaml")
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    assert_eq!(100, stdout.lines().count());
+    for shard in &["[shard 1/3]", "[shard 2/3]", "[shard 3/3]"] {
+        assert!(stdout.lines().any(|line| line.ends_with(shard)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn run_shard() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--shard")
+        .arg("2/3")
+        .arg("-c")
+        .arg("tests/test_data/more.yaml");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn duration_shards_need_timings() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--list")
+        .arg("--shard")
+        .arg("1/2")
+        .arg("--shard-strategy")
+        .arg("duration")
+        .arg("-c")
+        .arg("tests/test_data/more.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--shard-timings"));
+
+    Ok(())
+}
+
+#[test]
+fn list_shards_by_duration() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let timings = workdir.path().join("last-run.json");
+    std::fs::write(
+        &timings,
+        r#"{"configs": {"tests/test_data/last_failed.yaml": {"flaky": {"outcome": "passed", "duration_ms": 50}}}}"#,
+    )?;
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--list")
+        .arg("--shard")
+        .arg("1/2")
+        .arg("--shard-strategy")
+        .arg("duration")
+        .arg("--shard-timings")
+        .arg(&timings)
+        .arg("-c")
+        .arg("./tests/test_data/last_failed.yaml");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("flaky [shard 1/2]"))
+        .stderr(predicate::str::contains(
+            "warning: 1 of 2 tests have no recorded duration",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_shard() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--shard")
+        .arg("4/3")
+        .arg("-c")
+        .arg("tests/test_data/more.yaml");
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn run_slow() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--slowest")
+        .arg("1")
+        .arg("-c")
+        .arg("tests/test_data/slow.yaml");
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Slow test: 'sleepy' took"))
+        .stderr(predicate::str::contains("Slow test: 'quick'").not())
+        .stderr(predicate::str::contains("Slowest tests:\n  sleepy: "))
+        .stderr(predicate::str::contains("  quick: ").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_verbose_reports_passed_tests() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--verbose")
+        .arg("-c")
+        .arg("tests/test_data/slow.yaml");
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Passed test: 'sleepy' took "))
+        .stderr(predicate::str::contains("Passed test: 'quick' took "));
+
+    Ok(())
+}
+
+#[test]
+fn verify_slow() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/slow.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_show_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/show_output.yaml");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "[chatty] line one\n[chatty] line two\n",
+        ))
+        .stdout(predicate::str::contains("[quiet]").not())
+        .stderr(predicate::str::contains("Failed test: 'secret'"))
+        .stderr(predicate::str::contains("do not show this").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_nocapture() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--nocapture")
+        .arg("-c")
+        .arg("tests/test_data/show_output.yaml");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("[chatty] line one"))
+        .stdout(predicate::str::contains(
+            "[quiet] hidden unless --nocapture",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_show_output() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/show_output.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_huge_output() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let config = std::env::current_dir()?.join("tests/test_data/huge_output.yaml");
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.current_dir(workdir.path()).arg("-c").arg(&config);
+    let output = cmd.output()?;
+    let stderr = String::from_utf8(output.stderr)?;
+
+    assert!(!output.status.success());
+    assert!(stderr.contains(
+        "stdout:\n[... 1998976 bytes left out, full output in .baret/logs/huge.stdout]\n"
+    ));
+    // only the last kilobyte of the 2MB output is in the message
+    assert!(stderr.len() < 4096);
+
+    let log = std::fs::metadata(workdir.path().join(".baret/logs/huge.stdout"))?;
+    assert_eq!(2_000_000, log.len());
+
+    Ok(())
+}
+
+#[test]
+fn verify_huge_output() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/huge_output.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_phases() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/phases.yaml");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "[before breaks] cleaning up after a broken before",
+        ))
+        .stdout(predicate::str::contains("never runs").not())
+        .stderr(predicate::str::contains(
+            "Failed test: 'before breaks'\nphase: before\nexit code: 3",
+        ))
+        .stderr(predicate::str::contains(
+            "Failed test: 'test and after break'\nphase: test\nexit code: 1",
+        ))
+        .stderr(predicate::str::contains(
+            "phase: after\nexit code: 2\nstdout:\nafter is broken too",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_phases() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/phases.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_before_all_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/before_all_fails.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Failed setup\nphase: before_all\nexit code: 1\nstdout:\nno database",
+        ))
+        .stderr(predicate::str::contains(
+            "Error: before_all failed, 2 tests not run",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_before_all_fails() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/before_all_fails.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_missing_interpreter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c")
+        .arg("tests/test_data/missing_interpreter.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Failed test: 'no interpreter'\nphase: test\n",
+        ))
+        .stderr(predicate::str::contains(
+            "command: does-not-exist-interpreter -c \"print('hello')\"\ncwd: ",
+        ))
+        .stderr(predicate::str::contains(
+            "defined: test 'no interpreter' in tests/test_data/missing_interpreter.yaml:4",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_missing_interpreter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--verify")
+        .arg("-c")
+        .arg("tests/test_data/missing_interpreter.yaml");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "error: program `does-not-exist-interpreter` not found in PATH\n  --> tests/test_data/missing_interpreter.yaml:6:5\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_typo() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--verify")
+        .arg("-c")
+        .arg("tests/test_data/typo.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error: unknown key `shoud_fail` in `failing`\n  --> tests/test_data/typo.yaml:4:5\n",
+        ))
+        .stderr(predicate::str::contains("did you mean `should_fail`?"))
+        .stderr(predicate::str::contains("did you mean `timeout`?"))
+        .stderr(predicate::str::contains("did you mean `before_all`?"))
+        .stderr(predicate::str::contains(
+            "did you mean `max_test_concurrency`?",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn verify_invalid() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--verify")
+        .arg("-c")
+        .arg("tests/test_data/invalid.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("`timeout` must be more than 0"))
+        .stderr(predicate::str::contains(
+            "`setup_timeout` must be more than 0",
+        ))
+        .stderr(predicate::str::contains(
+            "`1ST_VAR` is not a valid environment variable name",
+        ))
+        .stderr(predicate::str::contains(
+            "program `not-a-real-program` not found in PATH",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn test_data_matches_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    let output = cmd.arg("--schema").output()?;
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let schema = jsonschema::JSONSchema::compile(&schema).expect("invalid schema");
+
+    // these configs are wrong on purpose, to test the error messages
+    let invalid = ["tests/test_data/invalid.yaml", "tests/test_data/typo.yaml"];
+
+    for path in glob::glob("tests/test_data/*.*")? {
+        let path = path?;
+        let source = std::fs::read_to_string(&path)?;
+        let data: serde_json::Value = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&source)?,
+            Some("json") => serde_json::from_str(&source)?,
+            Some("yaml") => {
+                serde_json::to_value(serde_yaml::from_str::<serde_yaml::Value>(&source)?)?
+            }
+            _ => continue,
+        };
+
+        if invalid
+            .iter()
+            .any(|invalid| path == std::path::Path::new(invalid))
+        {
+            assert!(
+                !schema.is_valid(&data),
+                "{} matches the schema",
+                path.display()
+            );
+        } else if let Err(errors) = schema.validate(&data) {
+            let errors: Vec<String> = errors.map(|error| error.to_string()).collect();
+            panic!("{} does not match the schema: {:?}", path.display(), errors);
+        };
+    }
+
+    Ok(())
+}
+
+#[test]
+fn run_toml() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/formats.toml");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn verify_toml() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/formats.toml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_json() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/formats.json");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn verify_json() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/formats.json")?;
+
+    Ok(())
+}
+
+#[test]
+fn prints_example_in_other_formats() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+
+    for format in &["yaml", "toml", "json"] {
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+        let output = cmd.arg("--example").arg("--format").arg(format).output()?;
+        assert!(output.status.success());
+
+        let config = workdir.path().join(format!("example.{}", format));
+        std::fs::write(&config, &output.stdout)?;
+
+        let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+        cmd.arg("--verify").arg("-c").arg(&config);
+        cmd.assert().success();
+    }
+
+    Ok(())
+}
+
+#[test]
+fn run_multiple_configs() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let test_data = std::env::current_dir()?.join("tests/test_data");
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.current_dir(workdir.path())
+        .arg("-c")
+        .arg(test_data.join("suite_a.yaml"))
+        .arg("-c")
+        .arg(test_data.join("suite_b.yaml"));
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed test: 'suite_a::fails'"))
+        .stderr(predicate::str::contains("suite_b::").not())
+        .stderr(predicate::str::contains(
+            "Error: 1 test had errors out of 3 tests",
+        ));
+    assert!(!workdir.path().join("a.setup").exists());
+    assert!(!workdir.path().join("b.setup").exists());
+
+    Ok(())
+}
+
+#[test]
+fn verify_multiple_configs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--verify")
+        .arg("-c")
+        .arg("tests/test_data/suite_a.yaml")
+        .arg("-c")
+        .arg("tests/test_data/typo.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("tests/test_data/typo.yaml:4:5"))
+        .stderr(predicate::str::contains("suite_a").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_from_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c")
+        .arg("-")
+        .write_stdin("test:\n  piped:\n    test: exit 0\n");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--list")
+        .arg("-c")
+        .arg("-")
+        .arg("-c")
+        .arg("tests/test_data/simple.yaml")
+        .write_stdin("test:\n  piped:\n    test: exit 0\n");
+    cmd.assert().success().stdout(predicate::str::starts_with(
+        "simple\n  hallo\nstdin\n  piped\n",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn run_discover() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("tests/test_data/discover");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn verify_discover() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.current_dir("tests/test_data/discover")
+        .arg("--discover")
+        .arg("--verify");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn list_discover() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--list").arg("tests/test_data/discover");
+    cmd.assert()
+        .success()
+        .stdout("api/\n  login\n    in its own dir\nbaret\n  root\nweb/\n  baret\n    a_test.sh\n");
+
+    Ok(())
+}
+
+#[test]
+fn run_executor() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/executor.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed test: 'mocked failure'"))
+        .stderr(predicate::str::contains("exit code: 3"))
+        .stderr(predicate::str::contains("boom"))
+        .stderr(predicate::str::contains(
+            "Error: 1 test had errors out of 3 tests",
+        ));
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs a running docker or podman daemon"]
+fn run_container() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/container.yaml");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs docker or podman"]
+fn verify_container() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/container.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn verify_executor() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/executor.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_interactive() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/interactive.yaml");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn verify_interactive() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/interactive.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_interactive_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/interactive_fails.yaml");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "step 1 failed: expect \"Password:\"\ntimed out after 300 ms\ntranscript:\nUsername: ",
+    ));
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("do not show this").not());
+
+    Ok(())
+}
+
+#[test]
+fn run_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/stdin.yaml");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn verify_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/stdin.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/snapshot.yaml");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn verify_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/snapshot.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_snapshot_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/snapshot_fails.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "snapshot tests/test_data/__snapshots__/changed.snap: the output changed",
+        ))
+        .stderr(predicate::str::contains(" one\n-2\n+two\n three\n"))
+        .stderr(predicate::str::contains(
+            "snapshot tests/test_data/__snapshots__/hidden.snap: `show_output: never` leaves no output to compare",
+        ))
+        .stderr(predicate::str::contains(
+            "snapshot tests/test_data/__snapshots__/new.snap: no snapshot yet",
+        ));
+    assert!(!std::path::Path::new("tests/test_data/__snapshots__/new.snap").exists());
+
+    Ok(())
+}
+
+#[test]
+fn verify_snapshot_orphan() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("--verify")
+        .arg("-c")
+        .arg("tests/test_data/snapshot_orphan.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "snapshot tests/test_data/__snapshots__/gone.snap does not belong to a test",
+        ))
+        .stderr(predicate::str::contains("kept.snap").not());
+
+    Ok(())
+}
+
+#[test]
+fn update_snapshots() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let config = workdir.path().join("baret.yaml");
+    let snapshot = workdir.path().join("__snapshots__/prints.snap");
+
+    std::fs::write(
+        &config,
+        "test:\n  prints:\n    test: \"echo one\"\n    snapshot: true\n",
+    )?;
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.arg("-c").arg(&config);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("no snapshot yet"));
+    assert!(!snapshot.exists());
+
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.arg("--update-snapshots").arg("-c").arg(&config);
+    cmd.assert().success();
+    assert!(std::fs::read_to_string(&snapshot)?.contains("--- stdout\none\n"));
+
+    std::fs::write(
+        &config,
+        "test:\n  prints:\n    test: \"echo two\"\n    snapshot: true\n",
+    )?;
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.arg("-c").arg(&config);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("-one\n+two\n"));
+
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+    cmd.arg("--update-snapshots").arg("-c").arg(&config);
+    cmd.assert().success();
+    assert!(std::fs::read_to_string(&snapshot)?.contains("--- stdout\ntwo\n"));
+
+    Ok(())
+}
+
+#[test]
+fn run_files() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/files.yaml");
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn verify_files() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/files.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_files_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/files_fails.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "files_exist tests/test_data/out/wrong/missing.txt: the file does not exist",
+        ))
+        .stderr(predicate::str::contains(
+            "files_absent tests/test_data/out/wrong/report.txt: the file exists",
+        ))
+        .stderr(predicate::str::contains(
+            "file_contains tests/test_data/out/wrong/report.txt: the content does not match",
+        ))
+        .stderr(predicate::str::contains(
+            "dir_matches tests/test_data/out/wrong/nested/b.txt: the file is different from the fixture\n--- fixture\n+++ file\n@@ -1 +1 @@\n-b\n+c\n",
+        ))
+        .stderr(predicate::str::contains(
+            "dir_matches tests/test_data/out/wrong/report.txt: the file is not in the fixture",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn run_services() -> Result<(), Box<dyn std::error::Error>> {
+    // the ports in the config can be taken, so the services get free ones
+    let workdir = tempfile::tempdir()?;
+    let config = workdir.path().join("services.yaml");
+    let (web, api) = (free_port()?, free_port()?);
+    std::fs::write(
+        &config,
+        std::fs::read_to_string("tests/test_data/services.yaml")?
+            .replace("48231", &web.to_string())
+            .replace("48232", &api.to_string()),
+    )?;
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg(&config);
+    cmd.assert().success();
+
+    // the services are stopped after the run
+    assert!(std::net::TcpStream::connect(("127.0.0.1", web)).is_err());
+    assert!(std::net::TcpStream::connect(("127.0.0.1", api)).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn verify_services() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/services.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_services_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/services_fails.yaml");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Failed setup\nphase: before_all\nservice `crashes` stopped with exit status: 3 before it was ready\nlog:\nno config found",
+        ))
+        .stderr(predicate::str::contains(
+            "Error: before_all failed, 1 test not run",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn run_services_exits_before_ready() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let config = workdir.path().join("services_exits.yaml");
+    let port = free_port()?;
+    std::fs::write(
+        &config,
+        std::fs::read_to_string("tests/test_data/services_exits.yaml")?
+            .replace("48233", &port.to_string()),
+    )?;
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg(&config);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "service `forks` stopped with exit status: 3 before it was ready",
+    ));
+
+    // the server the service started is stopped with it
+    assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn verify_services_exits() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/services_exits.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn run_interrupted_stops_the_services() -> Result<(), Box<dyn std::error::Error>> {
+    let workdir = tempfile::tempdir()?;
+    let config = workdir.path().join("interrupted.yaml");
+    let (service, test) = (free_port()?, free_port()?);
+    std::fs::write(
+        &config,
+        std::fs::read_to_string("tests/test_data/interrupted.yaml")?
+            .replace("48234", &service.to_string())
+            .replace("48235", &test.to_string()),
+    )?;
+    let start = std::time::Instant::now();
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin(assert_cmd::crate_name!()))
+        .arg("-c")
+        .arg(&config)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+
+    while std::net::TcpStream::connect(("127.0.0.1", test)).is_err() {
+        assert!(start.elapsed().as_secs() < 10, "the test did not start");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    // SAFETY: kill only sends a signal to the baret that was just started
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) };
+    let output = child.wait_with_output()?;
+
+    assert!(!output.status.success());
+    assert!(start.elapsed().as_secs() < 20, "the test was not cancelled");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("stopping the run"));
+    // the service and the server the test started are stopped
+    assert!(std::net::TcpStream::connect(("127.0.0.1", service)).is_err());
+    assert!(std::net::TcpStream::connect(("127.0.0.1", test)).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn verify_interrupted() -> Result<(), Box<dyn std::error::Error>> {
+    verify("tests/test_data/interrupted.yaml")?;
+
+    Ok(())
+}
+
+#[test]
+fn run_services_logs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(assert_cmd::crate_name!())?;
+
+    cmd.arg("-c").arg("tests/test_data/services_logs.yaml");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "service `talker` log:\nservice says hi\n",
+    ));
+
+    Ok(())
+}
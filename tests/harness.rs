@@ -0,0 +1 @@
+baret_lib::include_suite!("tests/test_data/simple.yaml");
@@ -0,0 +1 @@
+test -f baret.yaml